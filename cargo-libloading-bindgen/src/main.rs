@@ -1,5 +1,8 @@
 use anyhow::Error;
-use libloading_bindgen::BindingStrategy;
+use libloading_bindgen::{
+    BindingStrategy, Builder, CallTracing, ExternDeclarations, ThreadSafety,
+    WhenUnloaded,
+};
 use quote::ToTokens;
 use regex::Regex;
 use std::path::PathBuf;
@@ -19,7 +22,9 @@ fn main() -> Result<(), Error> {
     }
 
     let strategy = args.strategy()?;
-    let bindings = libloading_bindgen::generate_bindings(builder, &strategy)?
+    let bindings = args
+        .generator(builder)
+        .generate(&strategy)?
         .to_token_stream()
         .to_string();
 
@@ -40,8 +45,131 @@ struct Args {
         help = "Functions to include in the bindings (can be a regex)"
     )]
     whitelist_functions: Vec<String>,
+    #[structopt(
+        long = "optional-function",
+        multiple = true,
+        help = "Functions which may be missing from the library (can be a regex)"
+    )]
+    optional_functions: Vec<String>,
+    #[structopt(
+        long = "status-code-function",
+        multiple = true,
+        requires_all = &["status-code-error", "status-code-conversion"],
+        help = "Functions which return a status code, where zero means success (can be a regex)"
+    )]
+    status_code_functions: Vec<String>,
+    #[structopt(
+        long = "status-code-error",
+        requires = "status-code-conversion",
+        help = "The error type returned when a status code is non-zero"
+    )]
+    status_code_error: Option<String>,
+    #[structopt(
+        long = "status-code-conversion",
+        requires = "status-code-error",
+        help = "The function used to turn a non-zero status code into an error"
+    )]
+    status_code_conversion: Option<String>,
+    #[structopt(
+        long = "out-parameters",
+        multiple = true,
+        parse(try_from_str = parse_out_parameters),
+        help = "Functions whose last N arguments are out-parameters, written as \"<regex>=<N>\""
+    )]
+    out_parameters: Vec<(Regex, usize)>,
     #[structopt(short, long, help = "Where to write the bindings to")]
     output: Option<PathBuf>,
+    #[structopt(
+        long = "struct-name",
+        default_value = "Bindings",
+        help = "The name to use for the generated struct"
+    )]
+    struct_name: String,
+    #[structopt(
+        long = "name-collision-suffix",
+        help = "Append this to C functions whose names clash with the generated code"
+    )]
+    name_collision_suffix: Option<String>,
+    #[structopt(
+        long = "no-wrappers",
+        help = "Expose the function pointers as fields instead of generating methods"
+    )]
+    no_wrappers: bool,
+    #[structopt(
+        long = "emit-trait",
+        help = "Generate a trait describing the library's functions"
+    )]
+    emit_trait: bool,
+    #[structopt(
+        long = "mock",
+        help = "Generate a mock implementation of the trait, only compiled when this cfg is set (e.g. \"test\")"
+    )]
+    mock: Option<String>,
+    #[structopt(
+        long = "static-linking-feature",
+        help = "A cargo feature which makes the bindings use statically linked symbols instead of loading the library"
+    )]
+    static_linking_feature: Option<String>,
+    #[structopt(
+        long = "global",
+        help = "Generate functions for using a process-wide instance of the bindings"
+    )]
+    global: bool,
+    #[structopt(
+        long = "thread-safe",
+        help = "Mark the bindings as Send and Sync, explaining why the library is thread-safe"
+    )]
+    thread_safe: Option<String>,
+    #[structopt(
+        long = "serialise-calls",
+        conflicts_with_all = &["thread-safe", "no-wrappers"],
+        help = "Use a mutex to make sure only one function is called at a time"
+    )]
+    serialise_calls: bool,
+    #[structopt(
+        long = "trace-calls",
+        parse(try_from_str = parse_call_tracing),
+        help = "Log every call into the library using the \"log\" or \"tracing\" crate"
+    )]
+    trace_calls: Option<CallTracing>,
+    #[structopt(
+        long = "call-stats",
+        help = "Count the calls to each function and how long they take"
+    )]
+    call_stats: bool,
+    #[structopt(
+        long = "free-functions",
+        help = "Generate a module of free functions which call the global instance"
+    )]
+    free_functions: Option<String>,
+    #[structopt(
+        long = "free-functions-return-errors",
+        help = "Make the free functions return a Result instead of panicking when the library isn't loaded"
+    )]
+    free_functions_return_errors: bool,
+    #[structopt(
+        long = "library-name",
+        multiple = true,
+        help = "A file name the library might have, used to generate a load() constructor"
+    )]
+    library_names: Vec<String>,
+    #[structopt(
+        long = "library-dir",
+        multiple = true,
+        help = "A directory load() should look for the library in"
+    )]
+    library_directories: Vec<String>,
+    #[structopt(
+        long = "library-env-var",
+        multiple = true,
+        help = "An environment variable load() should check for the library's location"
+    )]
+    library_env_vars: Vec<String>,
+    #[structopt(
+        long = "keep-extern-declarations",
+        help = "Keep the original extern declarations so the library can also be linked statically"
+    )]
+    keep_extern_declarations: bool,
     #[structopt(
         parse(from_os_str),
         help = "The header file to generate bindings for"
@@ -50,6 +178,73 @@ struct Args {
 }
 
 impl Args {
+    fn generator(&self, bindgen: bindgen::Builder) -> Builder {
+        let mut generator = Builder::new(bindgen)
+            .struct_name(&self.struct_name)
+            .emit_wrappers(!self.no_wrappers)
+            .emit_trait(self.emit_trait)
+            .global_instance(self.global)
+            .call_stats(self.call_stats);
+
+        if let Some(suffix) = &self.name_collision_suffix {
+            generator = generator.name_collision_suffix(suffix);
+        }
+
+        if let (Some(error), Some(conversion)) =
+            (&self.status_code_error, &self.status_code_conversion)
+        {
+            generator = generator.status_codes(error, conversion);
+        }
+
+        if let Some(cfg) = &self.mock {
+            generator = generator.mock_cfg(cfg);
+        }
+
+        if let Some(feature) = &self.static_linking_feature {
+            generator = generator.static_linking_feature(feature);
+        }
+
+        if let Some(justification) = &self.thread_safe {
+            generator = generator.thread_safety(ThreadSafety::ThreadSafe {
+                justification: justification.clone(),
+            });
+        } else if self.serialise_calls {
+            generator = generator.thread_safety(ThreadSafety::Serialised);
+        }
+
+        if let Some(tracing) = self.trace_calls {
+            generator = generator.call_tracing(tracing);
+        }
+
+        if let Some(module) = &self.free_functions {
+            let when_unloaded = if self.free_functions_return_errors {
+                WhenUnloaded::ReturnError
+            } else {
+                WhenUnloaded::Panic
+            };
+            generator = generator.free_functions(module, when_unloaded);
+        }
+
+        for name in &self.library_names {
+            generator = generator.library_file_name(name);
+        }
+
+        for directory in &self.library_directories {
+            generator = generator.library_directory(directory);
+        }
+
+        for variable in &self.library_env_vars {
+            generator = generator.library_env_var(variable);
+        }
+
+        if self.keep_extern_declarations {
+            generator =
+                generator.extern_declarations(ExternDeclarations::KeepAll);
+        }
+
+        generator
+    }
+
     fn strategy(&self) -> Result<impl BindingStrategy, Error> {
         let mut whitelist_functions = Vec::new();
 
@@ -57,15 +252,52 @@ impl Args {
             whitelist_functions.push(Regex::new(pattern)?);
         }
 
+        let mut optional_functions = Vec::new();
+
+        for pattern in &self.optional_functions {
+            optional_functions.push(Regex::new(pattern)?);
+        }
+
+        let mut status_code_functions = Vec::new();
+
+        for pattern in &self.status_code_functions {
+            status_code_functions.push(Regex::new(pattern)?);
+        }
+
         Ok(Strategy {
             whitelist_functions,
+            optional_functions,
+            status_code_functions,
+            out_parameters: self.out_parameters.clone(),
         })
     }
 }
 
+fn parse_call_tracing(s: &str) -> Result<CallTracing, Error> {
+    match s {
+        "log" => Ok(CallTracing::Log),
+        "tracing" => Ok(CallTracing::Tracing),
+        _ => Err(anyhow::anyhow!("Expected \"log\" or \"tracing\"")),
+    }
+}
+
+fn parse_out_parameters(s: &str) -> Result<(Regex, usize), Error> {
+    match s.rfind('=') {
+        Some(index) => {
+            let pattern = Regex::new(&s[..index])?;
+            let count = s[index + 1..].parse()?;
+            Ok((pattern, count))
+        },
+        None => Err(anyhow::anyhow!("Expected \"<regex>=<count>\"")),
+    }
+}
+
 #[derive(Debug)]
 struct Strategy {
     whitelist_functions: Vec<Regex>,
+    optional_functions: Vec<Regex>,
+    status_code_functions: Vec<Regex>,
+    out_parameters: Vec<(Regex, usize)>,
 }
 
 impl BindingStrategy for Strategy {
@@ -76,4 +308,30 @@ impl BindingStrategy for Strategy {
             .iter()
             .any(|pattern| pattern.is_match(&name))
     }
+
+    fn is_optional(&self, item: &ForeignItemFn) -> bool {
+        let name = item.sig.ident.to_string();
+
+        self.optional_functions
+            .iter()
+            .any(|pattern| pattern.is_match(&name))
+    }
+
+    fn returns_status_code(&self, item: &ForeignItemFn) -> bool {
+        let name = item.sig.ident.to_string();
+
+        self.status_code_functions
+            .iter()
+            .any(|pattern| pattern.is_match(&name))
+    }
+
+    fn out_parameters(&self, item: &ForeignItemFn) -> usize {
+        let name = item.sig.ident.to_string();
+
+        self.out_parameters
+            .iter()
+            .find(|(pattern, _)| pattern.is_match(&name))
+            .map(|(_, count)| *count)
+            .unwrap_or(0)
+    }
 }
@@ -1,44 +1,153 @@
-use crate::BindingStrategy;
+use crate::{BindingStrategy, Config, Error, ExternDeclarations};
 use syn::{
     visit_mut::{self, VisitMut},
-    File, ForeignItem, ForeignItemFn, ForeignItemStatic, Item, ItemForeignMod,
-    LitStr,
+    Abi, Attribute, File, ForeignItem, ForeignItemFn, ForeignItemStatic, Ident,
+    Item, ItemForeignMod, Lit, LitStr, Meta, MetaNameValue,
 };
 
-pub(crate) fn extract_raw_bindings<S>(file: &mut File, strategy: &S) -> Bindings
+pub(crate) fn extract_raw_bindings<S>(
+    file: &mut File,
+    strategy: &S,
+    config: &Config,
+) -> Result<Bindings, Error>
 where
     S: BindingStrategy,
 {
     // extract the items we care about
     let mut generator = Generator::with_strategy(strategy);
+    generator.extern_declarations = config.extern_declarations;
 
     generator.visit_file_mut(file);
 
     let Generator {
-        functions, statics, ..
+        functions,
+        statics,
+        error,
+        ..
     } = generator;
 
-    Bindings { functions, statics }
+    match error {
+        Some(e) => Err(e),
+        None => Ok(Bindings { functions, statics }),
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Bindings {
     pub(crate) functions: Vec<ExternFunction>,
-    pub(crate) statics: Vec<ForeignItemStatic>,
+    pub(crate) statics: Vec<ExternStatic>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct ExternFunction {
-    pub(crate) abi: Option<LitStr>,
+    /// The calling convention used for this function's pointer, always
+    /// spelled out explicitly (e.g. `"C"` or `"system"`).
+    pub(crate) abi: LitStr,
+    /// The name to look up in the library's symbol table.
+    pub(crate) symbol: String,
+    /// The `#[link_name]` to use when linking against the library.
+    pub(crate) link_name: String,
+    /// The function's name in the original `extern` block, before the
+    /// strategy or name collision handling renamed it.
+    pub(crate) original_name: Ident,
+    /// Is it okay for this symbol to be missing from the library?
+    pub(crate) optional: bool,
+    /// Should we generate a `*_checked()` method which returns a `Result`?
+    pub(crate) status_code: bool,
+    /// The number of trailing arguments which are out-parameters.
+    pub(crate) out_parameters: usize,
     pub(crate) item: ForeignItemFn,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExternStatic {
+    /// The name to look up in the library's symbol table.
+    pub(crate) symbol: String,
+    /// The `#[link_name]` to use when linking against the library.
+    pub(crate) link_name: String,
+    pub(crate) item: ForeignItemStatic,
+}
+
+/// Figure out which symbol an extern item refers to, taking bindgen's
+/// `#[link_name = "..."]` attribute into account.
+///
+/// The `\u{1}` prefix is how bindgen tells `rustc` not to apply any
+/// platform-specific mangling, so we strip it before passing the name to the
+/// dynamic loader.
+pub(crate) fn symbol_name(attrs: &[Attribute], ident: &Ident) -> String {
+    link_name(attrs, ident)
+        .trim_start_matches('\u{1}')
+        .to_string()
+}
+
+/// The name an extern item is linked as, exactly as bindgen wrote it (i.e.
+/// including any `\u{1}` prefix).
+pub(crate) fn link_name(attrs: &[Attribute], ident: &Ident) -> String {
+    let link_name = attrs.iter().find_map(|attr| match attr.parse_meta() {
+        Ok(Meta::NameValue(MetaNameValue {
+            ref path,
+            lit: Lit::Str(ref name),
+            ..
+        })) if path.is_ident("link_name") => Some(name.value()),
+        _ => None,
+    });
+
+    link_name.unwrap_or_else(|| ident.to_string())
+}
+
+/// Should an item's original declaration be kept, given whether the strategy
+/// included it?
+fn keeps_declaration(mode: ExternDeclarations, included: bool) -> bool {
+    match mode {
+        ExternDeclarations::Remove => false,
+        ExternDeclarations::KeepExcluded => !included,
+        ExternDeclarations::KeepAll => true,
+    }
+}
+
+/// The calling conventions `rustc` accepts on stable.
+const KNOWN_ABIS: &[&str] = &[
+    "Rust",
+    "C",
+    "C-unwind",
+    "cdecl",
+    "cdecl-unwind",
+    "stdcall",
+    "stdcall-unwind",
+    "fastcall",
+    "fastcall-unwind",
+    "thiscall",
+    "thiscall-unwind",
+    "aapcs",
+    "aapcs-unwind",
+    "win64",
+    "win64-unwind",
+    "sysv64",
+    "sysv64-unwind",
+    "system",
+    "system-unwind",
+    "efiapi",
+];
+
+/// The ABI used by an `extern` block, remembering that `extern {}` is the same
+/// as `extern "C" {}`.
+fn block_abi(abi: &Abi) -> LitStr {
+    match &abi.name {
+        Some(name) => name.clone(),
+        None => LitStr::new("C", abi.extern_token.span),
+    }
+}
+
 #[derive(Debug)]
 struct Generator<'a, S> {
     functions: Vec<ExternFunction>,
-    statics: Vec<ForeignItemStatic>,
+    statics: Vec<ExternStatic>,
+    /// The ABI of the `extern` block we are currently inside.
     current_abi: Option<LitStr>,
     strategy: &'a S,
+    extern_declarations: ExternDeclarations,
+    /// The first problem we ran into, if any.
+    error: Option<Error>,
 }
 
 impl<'a, S: BindingStrategy> Generator<'a, S> {
@@ -48,6 +157,65 @@ impl<'a, S: BindingStrategy> Generator<'a, S> {
             functions: Vec::new(),
             statics: Vec::new(),
             current_abi: None,
+            extern_declarations: ExternDeclarations::Remove,
+            error: None,
+        }
+    }
+
+    fn extern_function(&mut self, mut item: ForeignItemFn) -> ExternFunction {
+        let default_symbol = symbol_name(&item.attrs, &item.sig.ident);
+        let symbol = self.strategy.symbol_name(&item, &default_symbol);
+
+        // keep bindgen's link name (and its "don't mangle this" marker)
+        // unless the strategy asked for a different symbol
+        let link_name = if symbol == default_symbol {
+            link_name(&item.attrs, &item.sig.ident)
+        } else {
+            symbol.clone()
+        };
+        let optional = self.strategy.is_optional(&item);
+        let status_code = self.strategy.returns_status_code(&item);
+        let out_parameters = self.strategy.out_parameters(&item);
+
+        let block_abi = self
+            .current_abi
+            .clone()
+            .expect("functions are only extracted from extern blocks");
+        let abi = match self.strategy.abi(&item, &block_abi.value()) {
+            Some(abi) if KNOWN_ABIS.contains(&abi.as_str()) => {
+                LitStr::new(&abi, block_abi.span())
+            },
+            Some(abi) => {
+                self.error.get_or_insert(Error::InvalidAbi {
+                    abi,
+                    function: item.sig.ident.to_string(),
+                });
+                block_abi
+            },
+            None => block_abi,
+        };
+
+        let original_name = item.sig.ident.clone();
+        let method_name = self.strategy.method_name(&item);
+        match syn::parse_str::<Ident>(&method_name) {
+            Ok(ident) => item.sig.ident = ident,
+            Err(_) => {
+                self.error.get_or_insert(Error::InvalidName {
+                    name: method_name,
+                    what: "generated method",
+                });
+            },
+        }
+
+        ExternFunction {
+            abi,
+            symbol,
+            link_name,
+            original_name,
+            optional,
+            status_code,
+            out_parameters,
+            item,
         }
     }
 }
@@ -57,11 +225,8 @@ impl<'a, S: BindingStrategy> VisitMut for Generator<'a, S> {
         visit_mut::visit_file_mut(self, file);
 
         // make sure we don't emit empty `extern "C" {}` blocks
-        file.items.retain(|item| match item {
-            Item::ForeignMod(extern_block) if extern_block.items.is_empty() => {
-                false
-            },
-            _ => true,
+        file.items.retain(|item| {
+            !matches!(item, Item::ForeignMod(extern_block) if extern_block.items.is_empty())
         });
     }
 
@@ -74,20 +239,48 @@ impl<'a, S: BindingStrategy> VisitMut for Generator<'a, S> {
         }
         self.visit_abi_mut(&mut extern_block.abi);
 
-        let current_abi = &extern_block.abi.name;
-        let items = std::mem::replace(&mut extern_block.items, Vec::new());
+        self.current_abi = Some(block_abi(&extern_block.abi));
+        let items = std::mem::take(&mut extern_block.items);
 
         for it in items.into_iter() {
-            match it {
+            let included = match &it {
                 ForeignItem::Fn(item) => {
-                    if self.strategy.should_include(&item) {
-                        self.functions.push(ExternFunction {
-                            abi: current_abi.clone(),
-                            item,
-                        });
-                    }
+                    Some(self.strategy.should_include(item))
+                },
+                ForeignItem::Static(item) => {
+                    Some(self.strategy.should_include_static(item))
                 },
-                ForeignItem::Static(s) => self.statics.push(s),
+                _ => None,
+            };
+
+            // some things need to stay around for people who statically link
+            let keep_declaration = match included {
+                Some(included) => {
+                    keeps_declaration(self.extern_declarations, included)
+                        || self.strategy.keep_extern_declaration(&it)
+                },
+                None => false,
+            };
+
+            if keep_declaration {
+                let mut declaration = it.clone();
+                self.visit_foreign_item_mut(&mut declaration);
+                extern_block.items.push(declaration);
+            }
+
+            match it {
+                ForeignItem::Fn(item) if included == Some(true) => {
+                    let func = self.extern_function(item);
+                    self.functions.push(func);
+                },
+                ForeignItem::Static(item) if included == Some(true) => {
+                    self.statics.push(ExternStatic {
+                        symbol: symbol_name(&item.attrs, &item.ident),
+                        link_name: link_name(&item.attrs, &item.ident),
+                        item,
+                    });
+                },
+                ForeignItem::Fn(_) | ForeignItem::Static(_) => {},
                 mut other => {
                     self.visit_foreign_item_mut(&mut other);
                     extern_block.items.push(other);
@@ -126,7 +319,9 @@ mod tests {
         "#;
         let mut file: File = syn::parse_str(src).unwrap();
 
-        let bindings = extract_raw_bindings(&mut file, &Always);
+        let bindings =
+            extract_raw_bindings(&mut file, &Always, &Config::default())
+                .unwrap();
 
         assert_eq!(file.attrs, file.attrs);
         // the `extern "C"` block should have been removed
@@ -134,11 +329,306 @@ mod tests {
             file.items,
             file.items
                 .iter()
-                .cloned()
                 .filter(|it| !matches!(it, Item::ForeignMod(_)))
+                .cloned()
                 .collect::<Vec<_>>()
         );
         assert_eq!(bindings.functions.len(), 1);
         assert_eq!(bindings.statics.len(), 1);
     }
+
+    #[test]
+    fn symbol_names_default_to_the_identifier() {
+        let item: ForeignItemFn = syn::parse_quote! {
+            pub fn add(left: c_int, right: c_int) -> c_int;
+        };
+
+        let got = symbol_name(&item.attrs, &item.sig.ident);
+
+        assert_eq!(got, "add");
+    }
+
+    #[test]
+    fn symbol_names_honour_link_name() {
+        let item: ForeignItemFn = syn::parse_quote! {
+            #[link_name = "\u{1}_ZN3foo3addEii"]
+            pub fn foo_add(left: c_int, right: c_int) -> c_int;
+        };
+
+        let got = symbol_name(&item.attrs, &item.sig.ident);
+
+        assert_eq!(got, "_ZN3foo3addEii");
+        // but the marker is kept when linking normally
+        assert_eq!(
+            link_name(&item.attrs, &item.sig.ident),
+            "\u{1}_ZN3foo3addEii"
+        );
+    }
+
+    #[test]
+    fn link_name_is_used_when_extracting_functions() {
+        let src = r#"
+        extern "C" {
+            #[link_name = "_add"]
+            pub fn add(left: c_int, right: c_int) -> c_int;
+        }
+        "#;
+        let mut file: File = syn::parse_str(src).unwrap();
+
+        let bindings =
+            extract_raw_bindings(&mut file, &Always, &Config::default())
+                .unwrap();
+
+        assert_eq!(bindings.functions[0].symbol, "_add");
+        assert_eq!(bindings.functions[0].link_name, "_add");
+        assert_eq!(bindings.functions[0].item.sig.ident, "add");
+    }
+
+    /// Prefixes every method with `my_`, loads `version_2` instead of
+    /// `version`, skips `ignored` and keeps the declaration for `add()`.
+    struct Custom;
+
+    impl BindingStrategy for Custom {
+        fn should_include(&self, _item: &ForeignItemFn) -> bool { true }
+
+        fn should_include_static(&self, item: &ForeignItemStatic) -> bool {
+            item.ident != "ignored"
+        }
+
+        fn method_name(&self, item: &ForeignItemFn) -> String {
+            format!("my_{}", item.sig.ident)
+        }
+
+        fn symbol_name(&self, item: &ForeignItemFn, link_name: &str) -> String {
+            if item.sig.ident == "version" {
+                String::from("version_2")
+            } else {
+                link_name.to_string()
+            }
+        }
+
+        fn keep_extern_declaration(&self, item: &ForeignItem) -> bool {
+            matches!(item, ForeignItem::Fn(f) if f.sig.ident == "add")
+        }
+    }
+
+    #[test]
+    fn strategy_hooks_are_used_when_extracting() {
+        let src = r#"
+        extern "C" {
+            pub static ignored: c_int;
+            pub static counter: c_int;
+            pub fn add(left: c_int, right: c_int) -> c_int;
+            pub fn version() -> c_int;
+        }
+        "#;
+        let mut file: File = syn::parse_str(src).unwrap();
+
+        let bindings =
+            extract_raw_bindings(&mut file, &Custom, &Config::default())
+                .unwrap();
+
+        assert_eq!(bindings.statics.len(), 1);
+        assert_eq!(bindings.statics[0].item.ident, "counter");
+        let add = &bindings.functions[0];
+        assert_eq!(add.item.sig.ident, "my_add");
+        assert_eq!(add.symbol, "add");
+        let version = &bindings.functions[1];
+        assert_eq!(version.item.sig.ident, "my_version");
+        assert_eq!(version.symbol, "version_2");
+        assert_eq!(version.link_name, "version_2");
+        // only the original declaration for add() should be left behind
+        let should_be: File = syn::parse_quote! {
+            extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+            }
+        };
+        assert_eq!(file, should_be);
+    }
+
+    #[test]
+    fn invalid_method_names_are_an_error() {
+        struct Invalid;
+
+        impl BindingStrategy for Invalid {
+            fn should_include(&self, _item: &ForeignItemFn) -> bool { true }
+
+            fn method_name(&self, _item: &ForeignItemFn) -> String {
+                String::from("not an identifier")
+            }
+        }
+
+        let mut file: File = syn::parse_quote! {
+            extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+            }
+        };
+
+        let got = extract_raw_bindings(&mut file, &Invalid, &Config::default())
+            .unwrap_err();
+
+        assert!(
+            matches!(got, Error::InvalidName { ref name, .. } if name == "not an identifier")
+        );
+    }
+
+    #[test]
+    fn excluded_declarations_can_be_kept() {
+        struct OnlyAdd;
+
+        impl BindingStrategy for OnlyAdd {
+            fn should_include(&self, item: &ForeignItemFn) -> bool {
+                item.sig.ident == "add"
+            }
+        }
+
+        let src = r#"
+        extern "C" {
+            pub fn add(left: c_int, right: c_int) -> c_int;
+            pub fn sub(left: c_int, right: c_int) -> c_int;
+        }
+        "#;
+        let config = Config {
+            extern_declarations: ExternDeclarations::KeepExcluded,
+            ..Default::default()
+        };
+        let mut file: File = syn::parse_str(src).unwrap();
+
+        let bindings =
+            extract_raw_bindings(&mut file, &OnlyAdd, &config).unwrap();
+
+        assert_eq!(bindings.functions.len(), 1);
+        let should_be: File = syn::parse_quote! {
+            extern "C" {
+                pub fn sub(left: c_int, right: c_int) -> c_int;
+            }
+        };
+        assert_eq!(file, should_be);
+    }
+
+    #[test]
+    fn all_declarations_can_be_kept() {
+        let src = r#"
+        extern "C" {
+            pub static VERSION: *const c_char;
+            pub fn add(left: c_int, right: c_int) -> c_int;
+        }
+        "#;
+        let config = Config {
+            extern_declarations: ExternDeclarations::KeepAll,
+            ..Default::default()
+        };
+        let mut file: File = syn::parse_str(src).unwrap();
+        let original = file.clone();
+
+        let bindings =
+            extract_raw_bindings(&mut file, &Always, &config).unwrap();
+
+        assert_eq!(bindings.functions.len(), 1);
+        assert_eq!(bindings.statics.len(), 1);
+        assert_eq!(file, original);
+    }
+
+    fn abis(bindings: &Bindings) -> Vec<String> {
+        bindings
+            .functions
+            .iter()
+            .map(|func| func.abi.value())
+            .collect()
+    }
+
+    #[test]
+    fn the_abi_of_each_extern_block_is_tracked() {
+        let src = r#"
+        extern {
+            pub fn implicit();
+        }
+        extern "C" {
+            pub fn c();
+        }
+        extern "system" {
+            pub fn system();
+        }
+        extern "stdcall" {
+            pub fn stdcall();
+        }
+        extern "C-unwind" {
+            pub fn c_unwind();
+        }
+        "#;
+        let mut file: File = syn::parse_str(src).unwrap();
+
+        let bindings =
+            extract_raw_bindings(&mut file, &Always, &Config::default())
+                .unwrap();
+
+        assert_eq!(
+            abis(&bindings),
+            &["C", "C", "system", "stdcall", "C-unwind"]
+        );
+    }
+
+    #[test]
+    fn the_strategy_can_override_the_abi() {
+        struct Override;
+
+        impl BindingStrategy for Override {
+            fn should_include(&self, _item: &ForeignItemFn) -> bool { true }
+
+            fn abi(
+                &self,
+                item: &ForeignItemFn,
+                block_abi: &str,
+            ) -> Option<String> {
+                assert_eq!(block_abi, "C");
+
+                if item.sig.ident == "callback" {
+                    Some(String::from("system"))
+                } else {
+                    None
+                }
+            }
+        }
+
+        let src = r#"
+        extern {
+            pub fn callback();
+            pub fn normal();
+        }
+        "#;
+        let mut file: File = syn::parse_str(src).unwrap();
+
+        let bindings =
+            extract_raw_bindings(&mut file, &Override, &Config::default())
+                .unwrap();
+
+        assert_eq!(abis(&bindings), &["system", "C"]);
+    }
+
+    #[test]
+    fn unknown_abis_are_an_error() {
+        struct Typo;
+
+        impl BindingStrategy for Typo {
+            fn should_include(&self, _item: &ForeignItemFn) -> bool { true }
+
+            fn abi(&self, _item: &ForeignItemFn, _: &str) -> Option<String> {
+                Some(String::from("sytem"))
+            }
+        }
+
+        let mut file: File = syn::parse_quote! {
+            extern "C" {
+                pub fn callback();
+            }
+        };
+
+        let got = extract_raw_bindings(&mut file, &Typo, &Config::default());
+
+        assert!(matches!(
+            got,
+            Err(Error::InvalidAbi { abi, function })
+                if abi == "sytem" && function == "callback"
+        ));
+    }
 }
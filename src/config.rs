@@ -0,0 +1,554 @@
+use crate::{BindingStrategy, Error};
+use syn::File;
+
+/// Options for tweaking the code generated by
+/// [`generate_bindings_with_config()`][crate::generate_bindings_with_config].
+///
+/// New options may be added in the future, so start from
+/// [`Config::default()`] or use the [`Builder`].
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct Config {
+    /// The name of the generated struct, `Bindings` by default.
+    ///
+    /// Giving each library its own name lets you `include!()` several sets of
+    /// bindings into the same module.
+    pub struct_name: String,
+    /// What to append to a C function or static's name when it clashes with
+    /// something in the generated code (e.g. a function called
+    /// `load_from_path`).
+    ///
+    /// This is also used when the [`BindingStrategy`] gives several items the
+    /// same name. Name collisions are reported as an
+    /// [`Error::NameCollision`] or [`Error::DuplicateName`] when this isn't
+    /// set.
+    pub name_collision_suffix: Option<String>,
+    /// The names used for the generated constructors.
+    pub constructor_names: ConstructorNames,
+    /// The visibility of the generated struct and its methods.
+    pub visibility: Visibility,
+    /// Should we generate methods which call each function?
+    ///
+    /// When this is `false` the function pointers are exposed as fields
+    /// instead, so it can't be combined with [`ThreadSafety::Serialised`].
+    pub emit_wrappers: bool,
+    /// Should we generate a `<struct_name>Api` trait with a method for each
+    /// function, implemented by the generated struct?
+    ///
+    /// This makes it possible to swap the real library out for a test double.
+    pub emit_trait: bool,
+    /// Generate a `Mock<struct_name>` implementing the API trait (see
+    /// [`Config::emit_trait`]) where each function calls a closure, only
+    /// compiled when this `cfg` predicate is true (e.g. `test` or
+    /// `feature = "mock"`).
+    ///
+    /// The trait is always generated when a mock is requested.
+    pub mock_cfg: Option<String>,
+    /// When this cargo feature is enabled, the generated struct uses the
+    /// symbols the crate was linked against instead of loading a library at
+    /// runtime.
+    ///
+    /// The constructors keep the same signatures but ignore their arguments,
+    /// so code using the bindings doesn't need to change. Linking to the
+    /// library is up to you (e.g. via a build script), and every function
+    /// must be present, even the optional ones.
+    pub static_linking_feature: Option<String>,
+    /// Where to look for the library, used to generate a `load()`
+    /// constructor.
+    pub library_search: Option<LibrarySearch>,
+    /// Generate `global()` and `set_global()` functions for using a single
+    /// process-wide instance of the bindings.
+    ///
+    /// The generated code uses `std::sync::OnceLock` (Rust 1.70 or newer) and
+    /// requires the generated struct to be `Send` and `Sync`.
+    pub global_instance: bool,
+    /// Generate a module of free functions with the same signatures as the
+    /// original `extern` functions, which call the global instance (see
+    /// [`Config::global_instance`]).
+    ///
+    /// The global instance is always generated when free functions are
+    /// requested.
+    pub free_functions: Option<FreeFunctions>,
+    /// What we promise about using the bindings from multiple threads.
+    pub thread_safety: ThreadSafety,
+    /// Log every call made through the wrapper methods, along with its
+    /// arguments and return value.
+    ///
+    /// The logging code is only generated when this is set.
+    pub call_tracing: Option<CallTracing>,
+    /// Keep track of how many times each function is called and how long it
+    /// took, available via a generated `stats()` method.
+    pub call_stats: bool,
+    /// How to turn the status code returned by functions marked with
+    /// [`BindingStrategy::returns_status_code()`] into an error.
+    pub status_codes: Option<StatusCodes>,
+    /// How strict we should be when a symbol is missing from the library.
+    pub symbol_resolution: SymbolResolution,
+    /// Which of bindgen's original `extern` declarations should be left in
+    /// the output.
+    pub extern_declarations: ExternDeclarations,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            struct_name: String::from("Bindings"),
+            name_collision_suffix: None,
+            constructor_names: ConstructorNames::default(),
+            visibility: Visibility::Public,
+            emit_wrappers: true,
+            emit_trait: false,
+            mock_cfg: None,
+            static_linking_feature: None,
+            library_search: None,
+            global_instance: false,
+            free_functions: None,
+            thread_safety: ThreadSafety::Unspecified,
+            call_tracing: None,
+            call_stats: false,
+            status_codes: None,
+            symbol_resolution: SymbolResolution::Strict,
+            extern_declarations: ExternDeclarations::Remove,
+        }
+    }
+}
+
+impl Config {
+    /// Do we need to generate the process-wide instance?
+    pub(crate) fn uses_global_instance(&self) -> bool {
+        self.global_instance || self.free_functions.is_some()
+    }
+}
+
+/// The module of free functions generated when
+/// [`Config::free_functions`] is set.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct FreeFunctions {
+    /// The module's name, `ffi` by default.
+    pub module: String,
+    /// What to do when a function is called before the global instance has
+    /// been set.
+    pub when_unloaded: WhenUnloaded,
+}
+
+impl Default for FreeFunctions {
+    fn default() -> Self {
+        FreeFunctions {
+            module: String::from("ffi"),
+            when_unloaded: WhenUnloaded::Panic,
+        }
+    }
+}
+
+/// How a free function reacts when it can't be called, either because the
+/// global instance hasn't been set or an optional function is missing.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum WhenUnloaded {
+    /// Panic, keeping each function's original signature.
+    Panic,
+    /// Wrap each function's return value in a `Result`.
+    ///
+    /// Functions which never return (`-> !`) still panic, because
+    /// `Result<!, _>` isn't stable.
+    ReturnError,
+}
+
+/// The error used by the `*_checked()` wrappers generated for functions which
+/// return a status code.
+///
+/// A status code of zero means success, anything else is passed to the
+/// conversion function.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct StatusCodes {
+    /// The error type (e.g. `crate::Error`).
+    pub error_type: String,
+    /// The path to a function which turns a non-zero status code into an
+    /// error (e.g. `crate::Error::from_status`).
+    pub conversion: String,
+}
+
+impl StatusCodes {
+    pub fn new<E, C>(error_type: E, conversion: C) -> Self
+    where
+        E: Into<String>,
+        C: Into<String>,
+    {
+        StatusCodes {
+            error_type: error_type.into(),
+            conversion: conversion.into(),
+        }
+    }
+}
+
+/// The names used for each of the generated constructors.
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct ConstructorNames {
+    /// Open the library at a particular path (`load_from_path`).
+    pub load_from_path: String,
+    /// Use an existing `libloading::Library` (`from_library`).
+    pub from_library: String,
+    /// Use an existing `libloading::os::unix::Library`
+    /// (`from_unix_library`).
+    pub from_unix_library: String,
+    /// Search for the library in the places specified by
+    /// [`Config::library_search`] (`load`).
+    pub load: String,
+}
+
+impl Default for ConstructorNames {
+    fn default() -> Self {
+        ConstructorNames {
+            load_from_path: String::from("load_from_path"),
+            from_library: String::from("from_library"),
+            from_unix_library: String::from("from_unix_library"),
+            load: String::from("load"),
+        }
+    }
+}
+
+/// The places the generated `load()` constructor will look for a library.
+///
+/// Environment variables are checked first, then each directory, and finally
+/// the bare file names are passed to the system's dynamic loader. An
+/// environment variable can either point at the library itself or at a
+/// directory containing it.
+///
+/// If the library can't be loaded, `load()` returns an error listing every
+/// path that was tried.
+#[derive(Debug, Default, Clone, PartialEq)]
+#[non_exhaustive]
+pub struct LibrarySearch {
+    /// The library's possible file names (e.g. `libfoo.so` and `libfoo.so.3`).
+    pub file_names: Vec<String>,
+    /// Directories the library might be in.
+    pub directories: Vec<String>,
+    /// Environment variables which may contain the library's location (e.g.
+    /// `FOO_LIBRARY_PATH`).
+    pub env_vars: Vec<String>,
+}
+
+/// The visibility used for generated items.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Visibility {
+    /// `pub`.
+    Public,
+    /// `pub(crate)`.
+    Crate,
+    /// Only visible to the module the bindings are included in.
+    Private,
+}
+
+/// Whether the generated struct can be shared between threads.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ThreadSafety {
+    /// Make no promises, the struct is only `Send` and `Sync` if its fields
+    /// happen to be.
+    Unspecified,
+    /// The library is safe to use from multiple threads, so we generate
+    /// `unsafe impl Send` and `Sync`, documented with the justification.
+    ThreadSafe {
+        /// Why it is okay to use the library from multiple threads.
+        justification: String,
+    },
+    /// The library isn't thread-safe, so every call goes through an internal
+    /// `Mutex`.
+    ///
+    /// This doesn't protect statics, or variadic functions because they are
+    /// called via a function pointer. It also needs
+    /// [`Config::emit_wrappers`], otherwise the function pointers would be
+    /// public fields that skip the lock.
+    ///
+    /// The mutex isn't reentrant, so a callback which calls back into the
+    /// bindings while the library is still running will deadlock.
+    Serialised,
+}
+
+/// The crate used to log calls into the library, at the `trace` level.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CallTracing {
+    /// The `log` crate.
+    Log,
+    /// The `tracing` crate.
+    Tracing,
+}
+
+/// How we react to a symbol that is missing from the library.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SymbolResolution {
+    /// Loading the library fails if a symbol is missing, unless
+    /// [`BindingStrategy::is_optional()`] says otherwise.
+    Strict,
+    /// Treat every function as optional.
+    Lenient,
+}
+
+/// Which functions and statics keep their original `extern` declaration.
+///
+/// Declarations are also kept whenever
+/// [`BindingStrategy::keep_extern_declaration()`] says so.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ExternDeclarations {
+    /// Remove every function and static, so they can only be used via the
+    /// generated struct.
+    Remove,
+    /// Keep the declarations for anything the [`BindingStrategy`] excluded,
+    /// so they can still be linked statically.
+    KeepExcluded,
+    /// Keep every declaration, for code which is built to both link
+    /// statically and load the library at runtime.
+    KeepAll,
+}
+
+/// A builder for generating bindings, combining the underlying
+/// [`bindgen::Builder`] with our own [`Config`].
+///
+/// ```rust,no_run
+/// # use libloading_bindgen::{BindingStrategy, Builder};
+/// # struct Everything;
+/// # impl BindingStrategy for Everything {
+/// #     fn should_include(&self, _: &syn::ForeignItemFn) -> bool { true }
+/// # }
+/// let bindgen = bindgen::builder().header("foo.h");
+///
+/// let bindings = Builder::new(bindgen)
+///     .struct_name("Foo")
+///     .generate(&Everything)?;
+/// # Ok::<(), libloading_bindgen::Error>(())
+/// ```
+#[derive(Debug)]
+pub struct Builder {
+    bindgen: bindgen::Builder,
+    config: Config,
+}
+
+impl Builder {
+    pub fn new(bindgen: bindgen::Builder) -> Self {
+        Builder::with_config(bindgen, Config::default())
+    }
+
+    pub fn with_config(bindgen: bindgen::Builder, config: Config) -> Self {
+        Builder { bindgen, config }
+    }
+
+    /// See [`Config::struct_name`].
+    pub fn struct_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.config.struct_name = name.into();
+        self
+    }
+
+    /// See [`Config::name_collision_suffix`].
+    pub fn name_collision_suffix<S: Into<String>>(mut self, suffix: S) -> Self {
+        self.config.name_collision_suffix = Some(suffix.into());
+        self
+    }
+
+    /// Use a different name for the `load_from_path()` constructor.
+    pub fn load_from_path_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.config.constructor_names.load_from_path = name.into();
+        self
+    }
+
+    /// Use a different name for the `from_library()` constructor.
+    pub fn from_library_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.config.constructor_names.from_library = name.into();
+        self
+    }
+
+    /// Use a different name for the `from_unix_library()` constructor.
+    pub fn from_unix_library_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.config.constructor_names.from_unix_library = name.into();
+        self
+    }
+
+    /// Use a different name for the `load()` constructor.
+    pub fn load_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.config.constructor_names.load = name.into();
+        self
+    }
+
+    /// Add one of the library's possible file names, see
+    /// [`LibrarySearch::file_names`].
+    pub fn library_file_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.library_search().file_names.push(name.into());
+        self
+    }
+
+    /// Add a directory to look for the library in, see
+    /// [`LibrarySearch::directories`].
+    pub fn library_directory<S: Into<String>>(mut self, directory: S) -> Self {
+        self.library_search().directories.push(directory.into());
+        self
+    }
+
+    /// Add an environment variable which may contain the library's location,
+    /// see [`LibrarySearch::env_vars`].
+    pub fn library_env_var<S: Into<String>>(mut self, variable: S) -> Self {
+        self.library_search().env_vars.push(variable.into());
+        self
+    }
+
+    fn library_search(&mut self) -> &mut LibrarySearch {
+        self.config
+            .library_search
+            .get_or_insert_with(LibrarySearch::default)
+    }
+
+    /// See [`Config::visibility`].
+    pub fn visibility(mut self, visibility: Visibility) -> Self {
+        self.config.visibility = visibility;
+        self
+    }
+
+    /// See [`Config::emit_wrappers`].
+    pub fn emit_wrappers(mut self, emit_wrappers: bool) -> Self {
+        self.config.emit_wrappers = emit_wrappers;
+        self
+    }
+
+    /// See [`Config::emit_trait`].
+    pub fn emit_trait(mut self, emit_trait: bool) -> Self {
+        self.config.emit_trait = emit_trait;
+        self
+    }
+
+    /// See [`Config::mock_cfg`].
+    pub fn mock_cfg<S: Into<String>>(mut self, cfg: S) -> Self {
+        self.config.mock_cfg = Some(cfg.into());
+        self
+    }
+
+    /// See [`Config::static_linking_feature`].
+    pub fn static_linking_feature<S: Into<String>>(
+        mut self,
+        feature: S,
+    ) -> Self {
+        self.config.static_linking_feature = Some(feature.into());
+        self
+    }
+
+    /// See [`Config::global_instance`].
+    pub fn global_instance(mut self, global_instance: bool) -> Self {
+        self.config.global_instance = global_instance;
+        self
+    }
+
+    /// Generate a module of free functions, see [`Config::free_functions`].
+    pub fn free_functions<S: Into<String>>(
+        mut self,
+        module: S,
+        when_unloaded: WhenUnloaded,
+    ) -> Self {
+        self.config.free_functions = Some(FreeFunctions {
+            module: module.into(),
+            when_unloaded,
+        });
+        self
+    }
+
+    /// See [`Config::thread_safety`].
+    pub fn thread_safety(mut self, thread_safety: ThreadSafety) -> Self {
+        self.config.thread_safety = thread_safety;
+        self
+    }
+
+    /// See [`Config::call_tracing`].
+    pub fn call_tracing(mut self, tracing: CallTracing) -> Self {
+        self.config.call_tracing = Some(tracing);
+        self
+    }
+
+    /// See [`Config::call_stats`].
+    pub fn call_stats(mut self, call_stats: bool) -> Self {
+        self.config.call_stats = call_stats;
+        self
+    }
+
+    /// Convert status codes into errors using `conversion`, see
+    /// [`Config::status_codes`].
+    pub fn status_codes<E, C>(mut self, error_type: E, conversion: C) -> Self
+    where
+        E: Into<String>,
+        C: Into<String>,
+    {
+        self.config.status_codes =
+            Some(StatusCodes::new(error_type, conversion));
+        self
+    }
+
+    /// See [`Config::symbol_resolution`].
+    pub fn symbol_resolution(mut self, resolution: SymbolResolution) -> Self {
+        self.config.symbol_resolution = resolution;
+        self
+    }
+
+    /// See [`Config::extern_declarations`].
+    pub fn extern_declarations(
+        mut self,
+        extern_declarations: ExternDeclarations,
+    ) -> Self {
+        self.config.extern_declarations = extern_declarations;
+        self
+    }
+
+    pub fn config(&self) -> &Config { &self.config }
+
+    /// Run bindgen and generate bindings for the functions selected by the
+    /// [`BindingStrategy`].
+    pub fn generate<S>(self, strategy: &S) -> Result<File, Error>
+    where
+        S: BindingStrategy,
+    {
+        crate::generate_bindings_with_config(
+            self.bindgen,
+            strategy,
+            &self.config,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_builder_updates_the_config() {
+        let builder = Builder::new(bindgen::builder())
+            .struct_name("Foo")
+            .name_collision_suffix("_")
+            .load_from_path_name("open")
+            .visibility(Visibility::Crate)
+            .emit_wrappers(false)
+            .status_codes("Error", "Error::new");
+
+        let got = builder.config();
+
+        assert_eq!(got.struct_name, "Foo");
+        assert_eq!(got.name_collision_suffix.as_deref(), Some("_"));
+        assert_eq!(got.constructor_names.load_from_path, "open");
+        assert_eq!(got.constructor_names.from_library, "from_library");
+        assert_eq!(got.visibility, Visibility::Crate);
+        assert!(!got.emit_wrappers);
+        assert_eq!(
+            got.status_codes,
+            Some(StatusCodes::new("Error", "Error::new"))
+        );
+    }
+
+    #[test]
+    fn library_search_locations_accumulate() {
+        let builder = Builder::new(bindgen::builder())
+            .library_file_name("libfoo.so")
+            .library_file_name("libfoo.so.3")
+            .library_directory("/opt/foo/lib")
+            .library_env_var("FOO_LIBRARY");
+
+        let got = builder.config().library_search.clone().unwrap();
+
+        assert_eq!(got.file_names, &["libfoo.so", "libfoo.so.3"]);
+        assert_eq!(got.directories, &["/opt/foo/lib"]);
+        assert_eq!(got.env_vars, &["FOO_LIBRARY"]);
+    }
+}
@@ -0,0 +1,155 @@
+use bindgen::Builder;
+use syn::parse::Error as ParseError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    /// Bindgen wasn't able to process the header file.
+    ///
+    /// Bindgen doesn't give us access to clang's diagnostics, so they will
+    /// have already been printed to stderr.
+    #[error(
+        "Bindgen was unable to generate bindings for {} (check clang's diagnostics for more)",
+        display_header(.header)
+    )]
+    BindgenFailed {
+        /// The header file bindings were being generated for.
+        header: Option<String>,
+        /// The extra arguments that were passed to clang.
+        clang_args: Vec<String>,
+    },
+    #[error(
+        "Unable to parse the bindings emitted by bindgen near `{snippet}`"
+    )]
+    Parse {
+        #[source]
+        error: ParseError,
+        /// The bit of generated code which failed to parse.
+        snippet: String,
+    },
+    /// One of the names in the [`Config`][crate::Config], or a name returned
+    /// by the [`BindingStrategy`][crate::BindingStrategy], isn't a valid
+    /// identifier.
+    #[error("\"{name}\" isn't a valid name for the {what}")]
+    InvalidName { name: String, what: &'static str },
+    /// The [`BindingStrategy`][crate::BindingStrategy] asked for an ABI that
+    /// `rustc` doesn't support.
+    #[error("\"{abi}\" isn't a known ABI (used by \"{function}\")")]
+    InvalidAbi {
+        abi: String,
+        /// The C function's name.
+        function: String,
+    },
+    /// The [`Config::mock_cfg`][crate::Config::mock_cfg] can't be used in a
+    /// `#[cfg]` attribute.
+    #[error("\"{cfg}\" isn't a valid cfg predicate")]
+    InvalidCfg { cfg: String },
+    /// A C function or static has the same name as something in the
+    /// generated code.
+    #[error(
+        "\"{name}\" clashes with a name used by the generated code (see `Config::name_collision_suffix`)"
+    )]
+    NameCollision { name: String },
+    /// The [`BindingStrategy`][crate::BindingStrategy] gave two functions or
+    /// statics the same name.
+    #[error(
+        "More than one function or static is called \"{name}\" (see `Config::name_collision_suffix`)"
+    )]
+    DuplicateName { name: String },
+    /// Two of the [`Config`][crate::Config]'s options can't be used together.
+    #[error("Using {first} isn't possible when {second}")]
+    IncompatibleOptions {
+        first: &'static str,
+        second: &'static str,
+    },
+    #[error("Unable to generate a wrapper for \"{function}\": {reason}")]
+    UnsupportedSignature {
+        /// The C function's name.
+        function: String,
+        reason: String,
+    },
+}
+
+impl Error {
+    pub(crate) fn bindgen_failed(builder: &Builder) -> Self {
+        let flags = builder.command_line_flags();
+
+        // bindgen puts the header first and any clang arguments after a "--"
+        let header = flags.first().filter(|f| !f.starts_with('-')).cloned();
+        let clang_args = flags
+            .iter()
+            .skip_while(|flag| *flag != "--")
+            .skip(1)
+            .cloned()
+            .collect();
+
+        Error::BindgenFailed { header, clang_args }
+    }
+
+    pub(crate) fn parse(error: ParseError, src: &str) -> Self {
+        let snippet = snippet(src, &error);
+        Error::Parse { error, snippet }
+    }
+}
+
+fn display_header(header: &Option<String>) -> String {
+    match header {
+        Some(header) => format!("\"{}\"", header),
+        None => String::from("the header"),
+    }
+}
+
+/// How many characters either side of the error to include in a snippet.
+const SNIPPET_CONTEXT: usize = 40;
+
+/// Get the bit of source code that a [`ParseError`] points at.
+///
+/// When `rustfmt` isn't available bindgen emits everything on a single line,
+/// so we only show the characters around the error's location.
+fn snippet(src: &str, error: &ParseError) -> String {
+    let start = error.span().start();
+
+    let line = match src.lines().nth(start.line.saturating_sub(1)) {
+        Some(line) => line,
+        None => return String::new(),
+    };
+
+    line.chars()
+        .skip(start.column.saturating_sub(SNIPPET_CONTEXT))
+        .take(2 * SNIPPET_CONTEXT)
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use syn::File;
+
+    #[test]
+    fn parse_errors_show_the_offending_code() {
+        let src = "fn first() {}\nfn second() -> -> u32 {}\nfn third() {}";
+        let error = syn::parse_str::<File>(src).unwrap_err();
+
+        let got = Error::parse(error, src);
+
+        match got {
+            Error::Parse { snippet, .. } => {
+                assert_eq!(snippet, "fn second() -> -> u32 {}")
+            },
+            other => panic!("Unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn snippets_from_long_lines_are_truncated() {
+        let padding = "fn f() {} ".repeat(20);
+        let src = format!("{}fn broken() -> -> u32 {{}} {}", padding, padding);
+        let error = syn::parse_str::<File>(&src).unwrap_err();
+
+        let got = snippet(&src, &error);
+
+        assert!(got.len() <= 2 * SNIPPET_CONTEXT);
+        assert!(got.contains("fn broken() -> -> u32"), "{}", got);
+    }
+}
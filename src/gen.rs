@@ -1,26 +1,360 @@
-use crate::bindings::{Bindings, ExternFunction};
-use proc_macro2::{Span, TokenStream};
+use crate::{
+    bindings::{Bindings, ExternFunction, ExternStatic},
+    CallTracing, Config, Error, FreeFunctions, LibrarySearch, StatusCodes,
+    SymbolResolution, ThreadSafety, WhenUnloaded,
+};
+use proc_macro2::{Delimiter, Group, Span, TokenStream};
 use quote::ToTokens;
+use std::collections::HashSet;
 use syn::{
+    punctuated::Punctuated,
     token::{Brace, Paren},
     Abi, AngleBracketedGenericArguments, AttrStyle, Attribute, BareFnArg,
-    Block, Expr, ExprCall, ExprField, ExprLit, ExprMethodCall, ExprParen,
-    ExprPath, ExprStruct, ExprTry, ExprUnary, Field, FieldValue, Fields,
-    FieldsNamed, FnArg, ForeignItemFn, GenericArgument, GenericParam, Generics,
-    Ident, ImplItemMethod, Item, ItemImpl, ItemStruct, Lit, LitByteStr, LitStr,
-    Local, Member, Pat, PatIdent, PatType, Path, PathArguments, PathSegment,
-    PredicateType, Receiver, ReturnType, Signature, Stmt, Token, TraitBound,
-    TraitBoundModifier, Type, TypeBareFn, TypeParam, TypeParamBound, TypePath,
-    UnOp, VisPublic, Visibility, WhereClause, WherePredicate,
+    Block, Expr, ExprCall, ExprClosure, ExprField, ExprLit, ExprMacro,
+    ExprMethodCall, ExprParen, ExprPath, ExprReference, ExprStruct, ExprTry,
+    ExprUnary, Field, FieldValue, Fields, FieldsNamed, FnArg, ForeignItem,
+    ForeignItemFn, ForeignItemStatic, GenericArgument, GenericParam, Generics,
+    Ident, ImplItem, ImplItemMethod, Item, ItemFn, ItemForeignMod, ItemImpl,
+    ItemStruct, ItemTrait, Lit, LitByteStr, LitStr, Local, Macro,
+    MacroDelimiter, Member, Meta, ParenthesizedGenericArguments, Pat, PatIdent,
+    PatType, Path, PathArguments, PathSegment, PredicateType, Receiver,
+    ReturnType, Signature, Stmt, Token, TraitBound, TraitBoundModifier,
+    TraitItem, TraitItemMethod, Type, TypeBareFn, TypeParam, TypeParamBound,
+    TypePath, TypePtr, TypeReference, TypeTraitObject, TypeTuple, UnOp,
+    VisPublic, VisRestricted, Visibility, WhereClause, WherePredicate,
 };
 
-pub(crate) fn append_new_bindings(items: &mut Vec<Item>, bindings: Bindings) {
-    items.push(bindings_vtable(&bindings).into());
-    items.push(bindings_constructor(&bindings).into());
-    items.push(bindings_methods(&bindings).into());
+pub(crate) fn append_new_bindings(
+    items: &mut Vec<Item>,
+    mut bindings: Bindings,
+    config: &Config,
+) -> Result<(), Error> {
+    if config.symbol_resolution == SymbolResolution::Lenient {
+        for func in &mut bindings.functions {
+            func.optional = true;
+        }
+    }
+
+    for func in &bindings.functions {
+        check_signature(func, config)?;
+    }
+
+    resolve_name_collisions(&mut bindings, config)?;
+
+    // the function pointers would be public, letting callers skip the lock
+    if config.thread_safety == ThreadSafety::Serialised && !config.emit_wrappers
+    {
+        return Err(Error::IncompatibleOptions {
+            first: "serialised thread safety",
+            second: "disabling wrapper methods",
+        });
+    }
+
+    match &config.static_linking_feature {
+        Some(feature) => {
+            let mut vtable = bindings_vtable(&bindings, config);
+            vtable.attrs.push(feature_cfg(feature, false));
+            let mut constructor = bindings_constructor(&bindings, config);
+            constructor.attrs.push(feature_cfg(feature, false));
+
+            items.push(vtable.into());
+            items.push(constructor.into());
+            items.push(linked_vtable(&bindings, config, feature).into());
+            items.push(linked_constructor(&bindings, config, feature).into());
+        },
+        None => {
+            items.push(bindings_vtable(&bindings, config).into());
+            items.push(bindings_constructor(&bindings, config).into());
+        },
+    }
+
+    if !bindings.statics.is_empty() {
+        items.extend(static_address(config));
+    }
+
+    if config.library_search.is_some() {
+        items.extend(load_error(config));
+    }
+
+    items.extend(thread_safety_impls(config));
+
+    if config.uses_global_instance() {
+        items.extend(global_instance(config));
+    }
+
+    if let Some(free_functions) = &config.free_functions {
+        items.push(free_functions_module(&bindings, config, free_functions));
+
+        if free_functions.when_unloaded == WhenUnloaded::ReturnError {
+            items.extend(unavailable_error(config));
+        }
+    }
+
+    if config.emit_wrappers {
+        items.push(bindings_methods(&bindings, config).into());
+    }
+
+    if config.call_stats {
+        items.extend(call_stats(config));
+    }
+
+    // the mock is only useful if there's a trait for it to implement
+    if config.emit_trait || config.mock_cfg.is_some() {
+        items.push(api_trait(&bindings, config).into());
+        items.push(api_impl(&bindings, config).into());
+    }
+
+    if let Some(cfg) = &config.mock_cfg {
+        items.push(mock_struct(&bindings, config, cfg).into());
+        items.push(mock_impl(&bindings, config, cfg).into());
+    }
+
+    Ok(())
+}
+
+/// The fields and methods we add to the generated struct, which C functions and
+/// statics aren't allowed to use.
+fn reserved_names(config: &Config) -> Vec<&str> {
+    let names = &config.constructor_names;
+
+    let mut reserved = vec![
+        "_library",
+        names.load_from_path.as_str(),
+        &names.from_library,
+        &names.from_unix_library,
+    ];
+
+    if config.library_search.is_some() {
+        reserved.push(&names.load);
+    }
+
+    if config.thread_safety == ThreadSafety::Serialised {
+        reserved.push("_lock");
+    }
+
+    if config.call_stats {
+        reserved.extend(&["_stats", "stats"]);
+    }
+
+    if config.uses_global_instance() {
+        reserved.extend(&["global", "set_global"]);
+
+        if config.library_search.is_some() {
+            reserved.push("load_global");
+        }
+    }
+
+    reserved
+}
+
+/// Make sure none of the C functions or statics share a name with something
+/// we generate, renaming them if the user asked us to.
+fn resolve_name_collisions(
+    bindings: &mut Bindings,
+    config: &Config,
+) -> Result<(), Error> {
+    let reserved = reserved_names(config);
+
+    let mut taken: HashSet<String> = bindings
+        .functions
+        .iter()
+        .map(|func| func.item.sig.ident.to_string())
+        .chain(bindings.statics.iter().map(|s| s.item.ident.to_string()))
+        .collect();
+
+    let names = bindings
+        .functions
+        .iter_mut()
+        .map(|func| &mut func.item.sig.ident)
+        .chain(bindings.statics.iter_mut().map(|s| &mut s.item.ident));
+
+    // a strategy may have given two functions the same name
+    let mut seen = HashSet::new();
+
+    for ident in names {
+        let name = ident.to_string();
+        let is_reserved = reserved.contains(&name.as_str());
+        if !is_reserved && seen.insert(name.clone()) {
+            continue;
+        }
+
+        let suffix = match config.name_collision_suffix.as_deref() {
+            Some(suffix) if !suffix.is_empty() => suffix,
+            _ if is_reserved => return Err(Error::NameCollision { name }),
+            _ => return Err(Error::DuplicateName { name }),
+        };
+
+        // raw identifiers (e.g. `r#type`) don't need to stay raw once the
+        // suffix is added
+        let mut renamed =
+            format!("{}{}", name.trim_start_matches("r#"), suffix);
+        while reserved.contains(&renamed.as_str()) || taken.contains(&renamed) {
+            renamed.push_str(suffix);
+        }
+
+        // the suffix was validated up front, but we might still have made a
+        // keyword
+        let renamed_ident = match syn::parse_str::<Ident>(&renamed) {
+            Ok(renamed) => Ident::new(&renamed.to_string(), ident.span()),
+            Err(_) => return Err(Error::NameCollision { name }),
+        };
+
+        taken.insert(renamed.clone());
+        seen.insert(renamed);
+        *ident = renamed_ident;
+    }
+
+    // the *_checked() and *_out() methods can't be renamed without
+    // surprising people
+    for func in &bindings.functions {
+        for name in extra_method_names(func, config) {
+            let name = name.to_string();
+
+            if reserved.contains(&name.as_str()) || !taken.insert(name.clone())
+            {
+                return Err(Error::NameCollision { name });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Make sure we know how to generate a wrapper for this function.
+fn check_signature(
+    func: &ExternFunction,
+    config: &Config,
+) -> Result<(), Error> {
+    let returns_never = returns_never(&func.item.sig.output);
+
+    let is_variadic = func.item.sig.variadic.is_some();
+
+    if func.optional && returns_never && !is_variadic {
+        return Err(Error::UnsupportedSignature {
+            function: func.item.sig.ident.to_string(),
+            reason: String::from(
+                "optional functions can't return `!` because `Option<!>` isn't stable",
+            ),
+        });
+    }
+
+    if func.status_code {
+        let reason = if is_variadic {
+            Some("variadic functions can't be wrapped")
+        } else if !returns_integer(&func.item.sig.output) {
+            Some("status codes must be integers")
+        } else if config.status_codes.is_none() {
+            Some("`Config::status_codes` is needed to convert its status code into an error")
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            return Err(Error::UnsupportedSignature {
+                function: func.item.sig.ident.to_string(),
+                reason: String::from(reason),
+            });
+        }
+    }
+
+    if func.out_parameters > 0 {
+        let inputs = &func.item.sig.inputs;
+
+        let reason = if is_variadic {
+            Some("variadic functions can't be wrapped")
+        } else if returns_never {
+            Some("it never returns, so its out-parameters can't be read")
+        } else if func.out_parameters > inputs.len() {
+            Some("it doesn't have enough arguments for its out-parameters")
+        } else if !inputs
+            .iter()
+            .skip(inputs.len() - func.out_parameters)
+            .all(|arg| out_parameter_type(arg).is_some())
+        {
+            Some("out-parameters must be `*mut T` pointers to a sized type")
+        } else {
+            None
+        };
+
+        if let Some(reason) = reason {
+            return Err(Error::UnsupportedSignature {
+                function: func.item.sig.ident.to_string(),
+                reason: String::from(reason),
+            });
+        }
+    }
+
+    Ok(())
+}
+
+/// The primitive integer types, and the `std::os::raw` aliases bindgen uses
+/// for C's integer types.
+const INTEGER_TYPES: &[&str] = &[
+    "i8",
+    "i16",
+    "i32",
+    "i64",
+    "i128",
+    "isize",
+    "u8",
+    "u16",
+    "u32",
+    "u64",
+    "u128",
+    "usize",
+    "c_char",
+    "c_schar",
+    "c_uchar",
+    "c_short",
+    "c_ushort",
+    "c_int",
+    "c_uint",
+    "c_long",
+    "c_ulong",
+    "c_longlong",
+    "c_ulonglong",
+];
+
+/// Can the return value be compared against `0`?
+fn returns_integer(output: &ReturnType) -> bool {
+    match output {
+        ReturnType::Type(_, ty) => match &**ty {
+            Type::Path(TypePath { qself: None, path }) => matches!(
+                path.segments.last(),
+                Some(last) if INTEGER_TYPES.iter().any(|name| last.ident == name)
+            ),
+            _ => false,
+        },
+        ReturnType::Default => false,
+    }
+}
+
+/// Get the `T` from an out-parameter's `*mut T`.
+fn out_parameter_type(arg: &FnArg) -> Option<&Type> {
+    let ptr = match arg {
+        FnArg::Typed(PatType { ty, .. }) => match &**ty {
+            Type::Ptr(ptr) if ptr.mutability.is_some() => ptr,
+            _ => return None,
+        },
+        FnArg::Receiver(_) => return None,
+    };
+
+    let is_void = match &*ptr.elem {
+        Type::Path(TypePath { path, .. }) => {
+            matches!(path.segments.last(), Some(last) if last.ident == "c_void")
+        },
+        _ => false,
+    };
+
+    // we can't create a c_void to pass in
+    if is_void {
+        None
+    } else {
+        Some(&ptr.elem)
+    }
 }
 
-fn bindings_vtable(bindings: &Bindings) -> ItemStruct {
+fn bindings_vtable(bindings: &Bindings, config: &Config) -> ItemStruct {
     let mut fields: Vec<Field> = Vec::new();
 
     fields.push(Field {
@@ -28,7 +362,7 @@ fn bindings_vtable(bindings: &Bindings) -> ItemStruct {
         ty: Type::Path(TypePath {
             path: Path {
                 leading_colon: Some(<Token![::]>::default()),
-                ..long_path(&["libloading", "Library"])
+                ..long_path(["libloading", "Library"])
             },
             qself: None,
         }),
@@ -43,31 +377,132 @@ fn bindings_vtable(bindings: &Bindings) -> ItemStruct {
         colon_token: Some(<Token!(:)>::default()),
     });
 
-    for func in &bindings.functions {
-        let sig = function_signature(func);
+    if config.call_stats {
+        fields.push(Field {
+            ident: Some(Ident::new("_stats", Span::call_site())),
+            ty: syn::parse_quote! {
+                ::std::collections::HashMap<
+                    &'static str,
+                    (::std::sync::atomic::AtomicU64, ::std::sync::atomic::AtomicU64),
+                >
+            },
+            vis: Visibility::Inherited,
+            attrs: vec![doc_comment(
+                " The number of calls and total nanoseconds spent in each function.",
+            )],
+            colon_token: Some(<Token!(:)>::default()),
+        });
+    }
+
+    if config.thread_safety == ThreadSafety::Serialised {
+        fields.push(Field {
+            ident: Some(Ident::new("_lock", Span::call_site())),
+            ty: syn::parse_quote!(::std::sync::Mutex<()>),
+            vis: Visibility::Inherited,
+            attrs: vec![doc_comment(
+                " Used to make sure only one function is called at a time.",
+            )],
+            colon_token: Some(<Token!(:)>::default()),
+        });
+    }
 
+    for func in &bindings.functions {
         fields.push(Field {
             colon_token: Some(<Token!(:)>::default()),
             ident: Some(func.item.sig.ident.clone()),
-            ty: Type::BareFn(sig),
-            vis: Visibility::Inherited,
+            ty: field_type(func),
+            vis: field_visibility(config),
+            attrs: Vec::new(),
+        });
+    }
+
+    for extern_static in &bindings.statics {
+        let item = &extern_static.item;
+
+        fields.push(Field {
+            colon_token: Some(<Token!(:)>::default()),
+            ident: Some(item.ident.clone()),
+            ty: static_address_type(item, config),
+            vis: field_visibility(config),
             attrs: Vec::new(),
         });
     }
 
     ItemStruct {
-        ident: Ident::new("Bindings", Span::call_site()),
+        ident: Ident::new(&config.struct_name, Span::call_site()),
         fields: Fields::Named(FieldsNamed {
             brace_token: Brace::default(),
             named: fields.into_iter().collect(),
         }),
         generics: Generics::default(),
-        vis: Visibility::Public(VisPublic {
-            pub_token: <Token![pub]>::default(),
-        }),
+        vis: visibility(config),
         semi_token: None,
         struct_token: <Token![struct]>::default(),
-        attrs: Vec::new(),
+        // statics keep their C names (e.g. `VERSION`)
+        attrs: vec![allow_lint("non_snake_case")],
+    }
+}
+
+/// `#[allow(...)]`
+fn allow_lint(lint: &str) -> Attribute {
+    let lint = Ident::new(lint, Span::call_site());
+
+    Attribute {
+        path: short_path("allow"),
+        tokens: quote::quote!((#lint)),
+        style: AttrStyle::Outer,
+        pound_token: Default::default(),
+        bracket_token: Default::default(),
+    }
+}
+
+fn visibility(config: &Config) -> Visibility {
+    match config.visibility {
+        crate::Visibility::Public => Visibility::Public(VisPublic {
+            pub_token: <Token![pub]>::default(),
+        }),
+        crate::Visibility::Crate => Visibility::Restricted(VisRestricted {
+            pub_token: <Token![pub]>::default(),
+            paren_token: Default::default(),
+            in_token: None,
+            path: Box::new(short_path("crate")),
+        }),
+        crate::Visibility::Private => Visibility::Inherited,
+    }
+}
+
+/// The visibility of items inside a module we generate, which need to be
+/// usable from wherever the bindings were included.
+fn nested_visibility(config: &Config) -> Visibility {
+    match config.visibility {
+        crate::Visibility::Private => Visibility::Restricted(VisRestricted {
+            pub_token: <Token![pub]>::default(),
+            paren_token: Default::default(),
+            in_token: None,
+            path: Box::new(short_path("super")),
+        }),
+        _ => visibility(config),
+    }
+}
+
+/// Symbols are normally accessed via a method, but if we aren't generating
+/// wrappers people will need to access the fields directly.
+fn field_visibility(config: &Config) -> Visibility {
+    if config.emit_wrappers {
+        Visibility::Inherited
+    } else {
+        visibility(config)
+    }
+}
+
+/// The type used to store a function pointer in our vtable.
+fn field_type(func: &ExternFunction) -> Type {
+    let sig = Type::BareFn(function_signature(func));
+
+    if func.optional {
+        option_of(sig)
+    } else {
+        sig
     }
 }
 
@@ -96,20 +531,102 @@ fn function_signature(func: &ExternFunction) -> TypeBareFn {
     } = sig;
 
     TypeBareFn {
-        fn_token: fn_token.clone(),
+        fn_token: *fn_token,
         lifetimes: None,
         unsafety: Some(<Token![unsafe]>::default()),
         abi: Some(Abi {
             extern_token: <Token![extern]>::default(),
-            name: func.abi.clone(),
+            name: Some(func.abi.clone()),
         }),
         inputs: inputs.iter().map(to_bare_fn_arg).collect(),
         output: output.clone(),
-        paren_token: paren_token.clone(),
+        paren_token: *paren_token,
         variadic: variadic.clone(),
     }
 }
 
+/// The type used to store a static's address (`*const T` for immutable
+/// statics, `*mut T` for `static mut`).
+fn static_pointer_type(item: &ForeignItemStatic) -> TypePtr {
+    TypePtr {
+        star_token: Default::default(),
+        const_token: match item.mutability {
+            Some(_) => None,
+            None => Some(Default::default()),
+        },
+        mutability: item.mutability,
+        elem: item.ty.clone(),
+    }
+}
+
+/// The name of the wrapper used to store a static's address.
+fn static_address_name(config: &Config) -> Ident {
+    Ident::new(
+        &format!("{}StaticAddress", config.struct_name),
+        Span::call_site(),
+    )
+}
+
+/// The type of a static's field (e.g. `BindingsStaticAddress<*const c_int>`).
+fn static_address_type(item: &ForeignItemStatic, config: &Config) -> Type {
+    let name = static_address_name(config);
+    let pointer = static_pointer_type(item);
+
+    syn::parse_quote!(#name<#pointer>)
+}
+
+/// Wrap a static's address so it can be stored in the generated struct.
+fn static_address_of(address: Expr, config: &Config) -> Expr {
+    let name = static_address_name(config);
+
+    syn::parse_quote!(#name(#address))
+}
+
+/// A wrapper around a static's address.
+///
+/// Raw pointers are neither `Send` nor `Sync`, so storing them directly would
+/// stop the generated struct from being shared between threads. The field is
+/// private so only the generated code can create one.
+fn static_address(config: &Config) -> Vec<Item> {
+    let vis = visibility(config);
+    let name = static_address_name(config);
+    let doc = LitStr::new(
+        &format!(
+            " The address of a static in the [`{}`]'s library.",
+            config.struct_name
+        ),
+        Span::call_site(),
+    );
+
+    vec![
+        syn::parse_quote! {
+            #[doc = #doc]
+            #[derive(Debug, Copy, Clone)]
+            #vis struct #name<P>(P);
+        },
+        syn::parse_quote! {
+            impl<P: Copy> #name<P> {
+                /// The static's address.
+                #vis fn as_ptr(&self) -> P { self.0 }
+            }
+        },
+        // Safety: the address doesn't change and reading through it is
+        // already unsafe.
+        syn::parse_quote! {
+            unsafe impl<T> Send for #name<*const T> {}
+        },
+        syn::parse_quote! {
+            unsafe impl<T> Sync for #name<*const T> {}
+        },
+        syn::parse_quote! {
+            unsafe impl<T> Send for #name<*mut T> {}
+        },
+        syn::parse_quote! {
+            unsafe impl<T> Sync for #name<*mut T> {}
+        },
+    ]
+}
+
 fn to_bare_fn_arg(arg: &FnArg) -> BareFnArg {
     match arg {
         FnArg::Receiver(_) => unreachable!(),
@@ -121,22 +638,34 @@ fn to_bare_fn_arg(arg: &FnArg) -> BareFnArg {
     }
 }
 
-fn bindings_constructor(bindings: &Bindings) -> ItemImpl {
-    let load_from_path = load_from_path(bindings);
+fn bindings_constructor(bindings: &Bindings, config: &Config) -> ItemImpl {
+    let load_from_path = load_from_path(config);
+    let from_library = from_library(bindings, config);
+    let from_unix_library = from_unix_library(config);
+
+    let mut items = vec![
+        load_from_path.into(),
+        from_library.into(),
+        from_unix_library.into(),
+    ];
+
+    if let Some(search) = &config.library_search {
+        items.push(load(search, config).into());
+    }
 
     ItemImpl {
-        attrs: Vec::new(),
+        attrs: vec![allow_lint("non_snake_case")],
         defaultness: None,
         unsafety: None,
         impl_token: Default::default(),
         generics: Generics::default(),
         trait_: None,
         self_ty: Box::new(Type::Path(TypePath {
-            path: short_path("Bindings"),
+            path: short_path(&config.struct_name),
             qself: None,
         })),
         brace_token: Default::default(),
-        items: vec![load_from_path.into()],
+        items,
     }
 }
 
@@ -169,14 +698,14 @@ where
     })
 }
 
-fn load_from_path(bindings: &Bindings) -> ImplItemMethod {
-    let sig = load_from_path_signature();
+fn load_from_path(config: &Config) -> ImplItemMethod {
+    let sig = load_from_path_signature(config);
 
     let library_new = Expr::Call(ExprCall {
         func: Box::new(Expr::Path(ExprPath {
             path: Path {
                 leading_colon: Some(<Token![::]>::default()),
-                ..long_path(&["libloading", "Library", "new"])
+                ..long_path(["libloading", "Library", "new"])
             },
             attrs: Vec::new(),
             qself: None,
@@ -212,98 +741,108 @@ fn load_from_path(bindings: &Bindings) -> ImplItemMethod {
         semi_token: <Token![;]>::default(),
     });
 
-    let mut stmts = vec![opening_the_library];
+    let block = Block {
+        brace_token: Default::default(),
+        stmts: vec![
+            opening_the_library,
+            Stmt::Expr(call_from_library(
+                Expr::Path(ExprPath {
+                    path: short_path("library"),
+                    attrs: Vec::new(),
+                    qself: None,
+                }),
+                config,
+            )),
+        ],
+    };
+
+    ImplItemMethod {
+        attrs: Vec::new(),
+        vis: visibility(config),
+        defaultness: None,
+        sig,
+        block,
+    }
+}
+
+/// `Self::from_library(library)`
+fn call_from_library(library: Expr, config: &Config) -> Expr {
+    Expr::Call(ExprCall {
+        func: Box::new(Expr::Path(ExprPath {
+            path: long_path(["Self", &config.constructor_names.from_library]),
+            attrs: Vec::new(),
+            qself: None,
+        })),
+        args: vec![library].into_iter().collect(),
+        paren_token: Default::default(),
+        attrs: Vec::new(),
+    })
+}
+
+fn from_library(bindings: &Bindings, config: &Config) -> ImplItemMethod {
+    let sig = from_library_signature(
+        &config.constructor_names.from_library,
+        Path {
+            leading_colon: Some(<Token![::]>::default()),
+            ..long_path(["libloading", "Library"])
+        },
+    );
+
     let library_variable = ExprPath {
         path: short_path("library"),
         attrs: Vec::new(),
         qself: None,
     };
 
-    let mut binding_struct_fields = vec![FieldValue {
-        colon_token: Some(Default::default()),
-        member: syn::Member::Named(Ident::new("_library", Span::call_site())),
-        expr: Expr::Path(ExprPath {
-            path: short_path("library"),
-            attrs: Vec::new(),
-            qself: None,
-        }),
-        attrs: Vec::new(),
-    }];
+    // Symbols are loaded directly inside the struct literal because `let`
+    // bindings can't shadow statics, and the original extern declarations
+    // may have been kept. That means `library` must be moved last.
+    let mut binding_struct_fields = Vec::new();
 
     for func in &bindings.functions {
-        let argument = func.item.sig.ident.to_string();
+        binding_struct_fields.push(load_symbol(
+            &library_variable,
+            &func.item.sig.ident,
+            &func.symbol,
+            func.optional,
+        ));
+    }
 
-        let library_get = Expr::MethodCall(ExprMethodCall {
-            attrs: Vec::new(),
-            receiver: Box::new(Expr::Path(library_variable.clone())),
-            dot_token: Default::default(),
-            method: Ident::new("get", Span::call_site()),
-            turbofish: None,
-            paren_token: Default::default(),
-            args: vec![Expr::Lit(ExprLit {
-                lit: Lit::ByteStr(LitByteStr::new(
-                    argument.as_bytes(),
-                    Span::call_site(),
-                )),
-                attrs: Vec::new(),
-            })]
-            .into_iter()
-            .collect(),
-        });
+    for extern_static in &bindings.statics {
+        let mut field = load_symbol(
+            &library_variable,
+            &extern_static.item.ident,
+            &extern_static.symbol,
+            false,
+        );
+        field.expr = static_address_of(field.expr, config);
+        binding_struct_fields.push(field);
+    }
 
-        let assignment = Stmt::Local(Local {
-            pat: Pat::Ident(PatIdent {
-                ident: Ident::new(&argument, Span::call_site()),
-                attrs: Vec::new(),
-                by_ref: None,
-                mutability: None,
-                subpat: None,
-            }),
-            init: Some((
-                <Token![=]>::default(),
-                Box::new(Expr::Unary(ExprUnary {
-                    attrs: Vec::new(),
-                    op: UnOp::Deref(Default::default()),
-                    expr: Box::new(Expr::Try(ExprTry {
-                        expr: Box::new(library_get),
-                        attrs: Vec::new(),
-                        question_token: <Token![?]>::default(),
-                    })),
-                })),
-            )),
-            attrs: Vec::new(),
-            let_token: <Token![let]>::default(),
-            semi_token: <Token![;]>::default(),
-        });
+    if config.thread_safety == ThreadSafety::Serialised {
+        binding_struct_fields.push(new_lock());
+    }
 
-        stmts.push(assignment);
-        binding_struct_fields.push(FieldValue {
-            colon_token: None,
-            member: syn::Member::Named(Ident::new(
-                &argument,
-                Span::call_site(),
-            )),
-            expr: Expr::Path(ExprPath {
-                path: Path::from(PathSegment {
-                    ident: Ident::new(&argument, Span::call_site()),
-                    arguments: PathArguments::None,
-                }),
-                attrs: Vec::new(),
-                qself: None,
-            }),
-            attrs: Vec::new(),
-        });
+    if config.call_stats {
+        binding_struct_fields.push(new_stats(bindings));
     }
 
+    binding_struct_fields.push(FieldValue {
+        colon_token: Some(Default::default()),
+        member: syn::Member::Named(Ident::new("_library", Span::call_site())),
+        expr: Expr::Path(library_variable),
+        attrs: Vec::new(),
+    });
+
     let binding_struct_literal = Expr::Struct(ExprStruct {
-        path: short_path("Bindings"),
+        path: short_path(&config.struct_name),
         fields: binding_struct_fields.into_iter().collect(),
         brace_token: Default::default(),
         dot2_token: None,
         rest: None,
         attrs: Vec::new(),
     });
-    stmts.push(Stmt::Expr(Expr::Call(ExprCall {
+    let ok = Expr::Call(ExprCall {
         func: Box::new(Expr::Path(ExprPath {
             path: short_path("Ok"),
             attrs: Vec::new(),
@@ -312,34 +851,204 @@ fn load_from_path(bindings: &Bindings) -> ImplItemMethod {
         args: vec![binding_struct_literal].into_iter().collect(),
         paren_token: Default::default(),
         attrs: Vec::new(),
-    })));
+    });
 
     let block = Block {
         brace_token: Default::default(),
-        stmts,
+        stmts: vec![Stmt::Expr(ok)],
     };
 
     ImplItemMethod {
         attrs: Vec::new(),
-        vis: Visibility::Public(VisPublic {
-            pub_token: <Token![pub]>::default(),
-        }),
+        vis: visibility(config),
         defaultness: None,
         sig,
         block,
     }
 }
 
+/// Generates the `name: *library.get(b"symbol")?` field initializer for the
+/// `Bindings { ... }` literal.
+///
+/// Optional symbols are loaded with
+/// `library.get(b"symbol").ok().map(|symbol| *symbol)` instead, so a missing
+/// symbol is stored as `None` rather than failing the whole load.
+fn load_symbol(
+    library_variable: &ExprPath,
+    name: &Ident,
+    symbol: &str,
+    optional: bool,
+) -> FieldValue {
+    let library_get = Expr::MethodCall(ExprMethodCall {
+        attrs: Vec::new(),
+        receiver: Box::new(Expr::Path(library_variable.clone())),
+        dot_token: Default::default(),
+        method: Ident::new("get", Span::call_site()),
+        turbofish: None,
+        paren_token: Default::default(),
+        args: vec![Expr::Lit(ExprLit {
+            lit: Lit::ByteStr(LitByteStr::new(
+                symbol.as_bytes(),
+                Span::call_site(),
+            )),
+            attrs: Vec::new(),
+        })]
+        .into_iter()
+        .collect(),
+    });
+
+    let value = if optional {
+        let symbol_variable = Ident::new("symbol", Span::call_site());
+        let deref_symbol = Expr::Closure(ExprClosure {
+            attrs: Vec::new(),
+            asyncness: None,
+            movability: None,
+            capture: None,
+            or1_token: Default::default(),
+            inputs: vec![Pat::Ident(PatIdent {
+                ident: symbol_variable.clone(),
+                attrs: Vec::new(),
+                by_ref: None,
+                mutability: None,
+                subpat: None,
+            })]
+            .into_iter()
+            .collect(),
+            or2_token: Default::default(),
+            output: ReturnType::Default,
+            body: Box::new(Expr::Unary(ExprUnary {
+                attrs: Vec::new(),
+                op: UnOp::Deref(Default::default()),
+                expr: Box::new(Expr::Path(ExprPath {
+                    path: Path::from(symbol_variable),
+                    attrs: Vec::new(),
+                    qself: None,
+                })),
+            })),
+        });
+
+        method_call(
+            method_call(library_get, "ok", Vec::new()),
+            "map",
+            vec![deref_symbol],
+        )
+    } else {
+        Expr::Unary(ExprUnary {
+            attrs: Vec::new(),
+            op: UnOp::Deref(Default::default()),
+            expr: Box::new(Expr::Try(ExprTry {
+                expr: Box::new(library_get),
+                attrs: Vec::new(),
+                question_token: <Token![?]>::default(),
+            })),
+        })
+    };
+
+    FieldValue {
+        colon_token: Some(Default::default()),
+        member: Member::Named(name.clone()),
+        expr: value,
+        attrs: Vec::new(),
+    }
+}
+
+fn method_call<A>(receiver: Expr, method: &str, args: A) -> Expr
+where
+    A: IntoIterator<Item = Expr>,
+{
+    Expr::MethodCall(ExprMethodCall {
+        attrs: Vec::new(),
+        receiver: Box::new(receiver),
+        dot_token: Default::default(),
+        method: Ident::new(method, Span::call_site()),
+        turbofish: None,
+        paren_token: Default::default(),
+        args: args.into_iter().collect(),
+    })
+}
+
 fn library_safety_docs() -> TokenStream {
+    doc_tokens("Safety: We need to keep the library handle around because our vtable's pointers point into it.")
+}
+
+fn doc_tokens(msg: &str) -> TokenStream {
     let mut tokens = TokenStream::new();
     <Token![=]>::default().to_tokens(&mut tokens);
-
-    let msg = "Safety: We need to keep the library handle around because our vtable's pointers point into it.";
     LitStr::new(msg, Span::call_site()).to_tokens(&mut tokens);
 
     tokens
 }
 
+/// `#[doc = "..."]`
+fn doc_comment(msg: &str) -> Attribute {
+    Attribute {
+        path: short_path("doc"),
+        tokens: doc_tokens(msg),
+        style: AttrStyle::Outer,
+        pound_token: Default::default(),
+        bracket_token: Default::default(),
+    }
+}
+
+/// The attributes to put on a method wrapping some extern item.
+///
+/// We keep the item's documentation (bindgen turns the header's comments into
+/// `#[doc]` attributes), `#[must_use]` and `#[deprecated]`, then add a line
+/// saying which symbol is being used.
+fn wrapper_attributes(original: &[Attribute], summary: &str) -> Vec<Attribute> {
+    let mut attrs: Vec<Attribute> = original
+        .iter()
+        .filter(|attr| attr.path.is_ident("doc"))
+        .cloned()
+        .collect();
+
+    if !attrs.is_empty() {
+        attrs.push(doc_comment(""));
+    }
+    attrs.push(doc_comment(summary));
+
+    attrs.extend(
+        original
+            .iter()
+            .filter(|attr| {
+                attr.path.is_ident("must_use")
+                    || attr.path.is_ident("deprecated")
+            })
+            .cloned(),
+    );
+
+    attrs
+}
+
+fn function_summary(func: &ExternFunction) -> String {
+    format!(
+        " Calls the `{}` function from the loaded library.",
+        func.symbol
+    )
+}
+
+/// Wrap a type in `Option<...>`.
+fn option_of(ty: Type) -> Type { generic_of("Option", ty) }
+
+/// Wrap a type in some generic type with a single parameter (e.g.
+/// `Box<...>`).
+fn generic_of(name: &str, ty: Type) -> Type {
+    Type::Path(TypePath {
+        path: Path::from(PathSegment {
+            ident: Ident::new(name, Span::call_site()),
+            arguments: PathArguments::AngleBracketed(
+                AngleBracketedGenericArguments {
+                    colon2_token: Default::default(),
+                    lt_token: Default::default(),
+                    gt_token: Default::default(),
+                    args: vec![GenericArgument::Type(ty)].into_iter().collect(),
+                },
+            ),
+        }),
+        qself: None,
+    })
+}
+
 fn generic_type<A>(name: &str, args: A) -> Path
 where
     A: IntoIterator<Item = Path>,
@@ -372,23 +1081,8 @@ where
 /// where
 ///   P: AsRef<::std::path::Path>
 /// ```
-fn load_from_path_signature() -> Signature {
-    let libloading_error = Path {
-        leading_colon: Some(<Token![::]>::default()),
-        ..long_path(&["libloading", "Error"])
-    };
-
-    let result_of_self_and_err = TypePath {
-        path: generic_type(
-            "Result",
-            vec![short_path("Self"), libloading_error],
-        ),
-        qself: None,
-    };
-    let output = ReturnType::Type(
-        <Token![->]>::default(),
-        Box::new(Type::Path(result_of_self_and_err)),
-    );
+fn load_from_path_signature(config: &Config) -> Signature {
+    let output = constructor_output();
 
     let as_ref_osstr = TypeParamBound::Trait(TraitBound {
         paren_token: None,
@@ -398,7 +1092,7 @@ fn load_from_path_signature() -> Signature {
             "AsRef",
             vec![Path {
                 leading_colon: Some(<Token![::]>::default()),
-                ..long_path(&["std", "ffi", "OsStr"])
+                ..long_path(["std", "ffi", "OsStr"])
             }],
         ),
     });
@@ -456,7 +1150,10 @@ fn load_from_path_signature() -> Signature {
         unsafety: Some(<Token![unsafe]>::default()),
         abi: None,
         fn_token: <Token![fn]>::default(),
-        ident: Ident::new("load_from_path", Span::call_site()),
+        ident: Ident::new(
+            &config.constructor_names.load_from_path,
+            Span::call_site(),
+        ),
         generics,
         paren_token: Default::default(),
         inputs,
@@ -465,8 +1162,140 @@ fn load_from_path_signature() -> Signature {
     }
 }
 
-fn defer_to_function(func: &ForeignItemFn) -> ImplItemMethod {
-    let mut inputs = func.sig.inputs.clone();
+/// `Result<Self, ::libloading::Error>`
+fn constructor_output() -> ReturnType {
+    let libloading_error = Path {
+        leading_colon: Some(<Token![::]>::default()),
+        ..long_path(["libloading", "Error"])
+    };
+
+    let result_of_self_and_err = TypePath {
+        path: generic_type(
+            "Result",
+            vec![short_path("Self"), libloading_error],
+        ),
+        qself: None,
+    };
+
+    ReturnType::Type(
+        <Token![->]>::default(),
+        Box::new(Type::Path(result_of_self_and_err)),
+    )
+}
+
+/// `unsafe fn $name(library: $library_type) -> Result<Self,
+/// ::libloading::Error>`
+fn from_library_signature(name: &str, library_type: Path) -> Signature {
+    let inputs = vec![FnArg::Typed(PatType {
+        attrs: Vec::new(),
+        colon_token: <Token![:]>::default(),
+        pat: Box::new(Pat::Ident(PatIdent {
+            attrs: Vec::new(),
+            by_ref: None,
+            ident: Ident::new("library", Span::call_site()),
+            mutability: None,
+            subpat: None,
+        })),
+        ty: Box::new(Type::Path(TypePath {
+            path: library_type,
+            qself: None,
+        })),
+    })]
+    .into_iter()
+    .collect();
+
+    Signature {
+        constness: None,
+        asyncness: None,
+        unsafety: Some(<Token![unsafe]>::default()),
+        abi: None,
+        fn_token: <Token![fn]>::default(),
+        ident: Ident::new(name, Span::call_site()),
+        generics: Generics::default(),
+        paren_token: Default::default(),
+        inputs,
+        variadic: None,
+        output: constructor_output(),
+    }
+}
+
+/// A constructor which accepts a library opened using the platform-specific
+/// API (e.g. with custom `dlopen()` flags like `RTLD_GLOBAL`).
+///
+/// ```rust,ignore
+/// #[cfg(unix)]
+/// pub unsafe fn from_unix_library(
+///     library: ::libloading::os::unix::Library,
+/// ) -> Result<Self, ::libloading::Error> {
+///     Self::from_library(library.into())
+/// }
+/// ```
+fn from_unix_library(config: &Config) -> ImplItemMethod {
+    let sig = from_library_signature(
+        &config.constructor_names.from_unix_library,
+        Path {
+            leading_colon: Some(<Token![::]>::default()),
+            ..long_path(["libloading", "os", "unix", "Library"])
+        },
+    );
+
+    let library_into = method_call(
+        Expr::Path(ExprPath {
+            path: short_path("library"),
+            attrs: Vec::new(),
+            qself: None,
+        }),
+        "into",
+        Vec::new(),
+    );
+
+    ImplItemMethod {
+        attrs: vec![Attribute {
+            path: short_path("cfg"),
+            tokens: quote::quote!((unix)),
+            style: AttrStyle::Outer,
+            pound_token: Default::default(),
+            bracket_token: Default::default(),
+        }],
+        vis: visibility(config),
+        defaultness: None,
+        sig,
+        block: Block {
+            brace_token: Default::default(),
+            stmts: vec![Stmt::Expr(call_from_library(library_into, config))],
+        },
+    }
+}
+
+fn defer_to_function(
+    extern_fn: &ExternFunction,
+    config: &Config,
+) -> ImplItemMethod {
+    let func = &extern_fn.item;
+
+    if func.sig.variadic.is_some() {
+        // Only foreign functions can be variadic, so the best we can do is
+        // give people the function pointer and let them call it.
+        return function_pointer_getter(extern_fn, config);
+    }
+
+    let arguments = normalised_arguments(&func.sig);
+
+    let mut inputs: Punctuated<FnArg, Token![,]> = arguments
+        .iter()
+        .map(|(name, input)| {
+            FnArg::Typed(PatType {
+                pat: Box::new(Pat::Ident(PatIdent {
+                    ident: name.clone(),
+                    attrs: Vec::new(),
+                    by_ref: None,
+                    mutability: None,
+                    subpat: None,
+                })),
+                ..(*input).clone()
+            })
+        })
+        .collect();
     inputs.insert(
         0,
         FnArg::Receiver(Receiver {
@@ -477,83 +1306,2464 @@ fn defer_to_function(func: &ForeignItemFn) -> ImplItemMethod {
         }),
     );
 
+    // optional functions return `None` when the symbol wasn't available
+    let output = if extern_fn.optional {
+        let return_type = match &func.sig.output {
+            ReturnType::Default => Type::Tuple(TypeTuple {
+                paren_token: Default::default(),
+                elems: Default::default(),
+            }),
+            ReturnType::Type(_, ty) => (**ty).clone(),
+        };
+        ReturnType::Type(
+            <Token![->]>::default(),
+            Box::new(option_of(return_type)),
+        )
+    } else {
+        func.sig.output.clone()
+    };
+
     let sig = Signature {
         inputs,
+        output,
         unsafety: Some(<Token![unsafe]>::default()),
         ..func.sig.clone()
     };
 
-    let mut call_args = Vec::new();
-    for input in &func.sig.inputs {
-        if let FnArg::Typed(PatType { ref pat, .. }) = input {
-            if let Pat::Ident(PatIdent { ref ident, .. }) = **pat {
-                call_args.push(Expr::Path(ExprPath {
-                    path: Path::from(PathSegment {
-                        ident: ident.clone(),
-                        arguments: PathArguments::None,
-                    }),
-                    qself: None,
-                    attrs: Vec::new(),
-                }));
-            }
-        }
+    let call_args = arguments.iter().map(|(name, _)| {
+        Expr::Path(ExprPath {
+            path: Path::from(name.clone()),
+            qself: None,
+            attrs: Vec::new(),
+        })
+    });
+
+    let mut field = Expr::Field(ExprField {
+        base: Box::new(Expr::Path(ExprPath {
+            path: short_path("self"),
+            qself: None,
+            attrs: Vec::new(),
+        })),
+        attrs: Vec::new(),
+        dot_token: Default::default(),
+        member: Member::Named(func.sig.ident.clone()),
+    });
+
+    if extern_fn.optional {
+        // (self.function?)(...)
+        field = Expr::Try(ExprTry {
+            expr: Box::new(field),
+            attrs: Vec::new(),
+            question_token: <Token![?]>::default(),
+        });
     }
 
-    let field = Expr::Paren(ExprParen {
-        expr: Box::new(Expr::Field(ExprField {
-            base: Box::new(Expr::Path(ExprPath {
-                path: short_path("self"),
-                qself: None,
-                attrs: Vec::new(),
-            })),
+    let mut call = Expr::Call(ExprCall {
+        func: Box::new(Expr::Paren(ExprParen {
+            expr: Box::new(field),
             attrs: Vec::new(),
-            dot_token: Default::default(),
-            member: Member::Named(func.sig.ident.clone()),
+            paren_token: Default::default(),
         })),
+        args: call_args.into_iter().collect(),
         attrs: Vec::new(),
         paren_token: Default::default(),
     });
 
-    let block = Block {
-        brace_token: Default::default(),
-        stmts: vec![Stmt::Expr(Expr::Call(ExprCall {
-            func: Box::new(field),
-            args: call_args.into_iter().collect(),
+    if extern_fn.optional {
+        call = Expr::Call(ExprCall {
+            func: Box::new(Expr::Path(ExprPath {
+                path: short_path("Some"),
+                attrs: Vec::new(),
+                qself: None,
+            })),
+            args: vec![call].into_iter().collect(),
             attrs: Vec::new(),
             paren_token: Default::default(),
-        }))],
+        });
+    }
+
+    if config.call_stats {
+        call = recorded_call(extern_fn, call, &sig.output);
+    }
+
+    let mut stmts = Vec::new();
+
+    if config.thread_safety == ThreadSafety::Serialised {
+        stmts.push(acquire_lock(quote::quote!(self)));
+    }
+
+    match config.call_tracing {
+        Some(tracing) => stmts.extend(traced_call(
+            tracing,
+            extern_fn,
+            &arguments,
+            call,
+            &sig.output,
+        )),
+        None => stmts.push(Stmt::Expr(call)),
+    }
+
+    let block = Block {
+        brace_token: Default::default(),
+        stmts,
     };
 
     ImplItemMethod {
-        attrs: Vec::new(),
-        vis: Visibility::Public(VisPublic {
-            pub_token: <Token![pub]>::default(),
-        }),
+        attrs: wrapper_attributes(&func.attrs, &function_summary(extern_fn)),
+        vis: visibility(config),
         defaultness: None,
         sig,
         block,
     }
 }
 
-fn bindings_methods(bindings: &Bindings) -> ItemImpl {
-    let mut methods = Vec::new();
+/// Give every argument a name we can use when forwarding it to the function
+/// pointer.
+///
+/// Arguments are usually already named, but we need to handle things like `_`
+/// and duplicate names.
+fn normalised_arguments(sig: &Signature) -> Vec<(Ident, &PatType)> {
+    let mut names = HashSet::new();
+    let mut arguments = Vec::new();
 
-    for func in &bindings.functions {
-        methods.push(defer_to_function(&func.item).into());
+    for (i, input) in sig.inputs.iter().enumerate() {
+        let input = match input {
+            FnArg::Typed(input) => input,
+            FnArg::Receiver(_) => unreachable!("FFI functions can't take self"),
+        };
+
+        let name = match &*input.pat {
+            Pat::Ident(PatIdent { ident, .. })
+                if ident != "self" && !names.contains(ident) =>
+            {
+                ident.clone()
+            },
+            _ => unused_argument_name(&names, i + 1),
+        };
+
+        names.insert(name.clone());
+        arguments.push((name, input));
     }
 
-    ItemImpl {
-        attrs: Vec::new(),
-        brace_token: Default::default(),
-        defaultness: None,
-        generics: Generics::default(),
-        self_ty: Box::new(Type::Path(TypePath {
-            path: short_path("Bindings"),
+    arguments
+}
+
+fn unused_argument_name(names: &HashSet<Ident>, index: usize) -> Ident {
+    let mut candidate = format!("arg{}", index);
+
+    while names.iter().any(|name| *name == candidate) {
+        candidate.push('_');
+    }
+
+    Ident::new(&candidate, Span::call_site())
+}
+
+/// Generate a method which returns the function pointer as-is.
+///
+/// ```rust,ignore
+/// pub fn printf(&self) -> unsafe extern "C" fn(*const c_char, ...) -> c_int {
+///     self.printf
+/// }
+/// ```
+fn function_pointer_getter(
+    func: &ExternFunction,
+    config: &Config,
+) -> ImplItemMethod {
+    let field = Expr::Field(ExprField {
+        base: Box::new(Expr::Path(ExprPath {
+            path: short_path("self"),
             qself: None,
+            attrs: Vec::new(),
         })),
-        trait_: None,
+        attrs: Vec::new(),
+        dot_token: Default::default(),
+        member: Member::Named(func.item.sig.ident.clone()),
+    });
+
+    let sig = Signature {
+        constness: None,
+        asyncness: None,
         unsafety: None,
-        impl_token: Default::default(),
-        items: methods,
+        abi: None,
+        fn_token: <Token![fn]>::default(),
+        ident: func.item.sig.ident.clone(),
+        generics: Generics::default(),
+        paren_token: Default::default(),
+        inputs: vec![FnArg::Receiver(Receiver {
+            attrs: Vec::new(),
+            reference: Some((<Token![&]>::default(), None)),
+            mutability: None,
+            self_token: <Token![self]>::default(),
+        })]
+        .into_iter()
+        .collect(),
+        variadic: None,
+        output: ReturnType::Type(
+            <Token![->]>::default(),
+            Box::new(field_type(func)),
+        ),
+    };
+
+    ImplItemMethod {
+        attrs: wrapper_attributes(&func.item.attrs, &function_summary(func)),
+        vis: visibility(config),
+        defaultness: None,
+        sig,
+        block: Block {
+            brace_token: Default::default(),
+            stmts: vec![Stmt::Expr(field)],
+        },
+    }
+}
+
+/// Generate a getter for an extern static.
+///
+/// Immutable statics are exposed as a shared reference, while a `static mut`
+/// hands out the raw pointer so the caller can decide how to synchronise
+/// access.
+fn static_accessor(
+    extern_static: &ExternStatic,
+    config: &Config,
+) -> ImplItemMethod {
+    let item = &extern_static.item;
+    let field = method_call(
+        Expr::Field(ExprField {
+            base: Box::new(Expr::Path(ExprPath {
+                path: short_path("self"),
+                qself: None,
+                attrs: Vec::new(),
+            })),
+            attrs: Vec::new(),
+            dot_token: Default::default(),
+            member: Member::Named(item.ident.clone()),
+        }),
+        "as_ptr",
+        Vec::new(),
+    );
+
+    let (return_type, body) = match item.mutability {
+        Some(_) => (Type::Ptr(static_pointer_type(item)), field),
+        None => (
+            Type::Reference(TypeReference {
+                and_token: Default::default(),
+                lifetime: None,
+                mutability: None,
+                elem: item.ty.clone(),
+            }),
+            Expr::Reference(ExprReference {
+                and_token: Default::default(),
+                raw: Default::default(),
+                mutability: None,
+                expr: Box::new(Expr::Unary(ExprUnary {
+                    attrs: Vec::new(),
+                    op: UnOp::Deref(Default::default()),
+                    expr: Box::new(field),
+                })),
+                attrs: Vec::new(),
+            }),
+        ),
+    };
+
+    let sig = Signature {
+        constness: None,
+        asyncness: None,
+        unsafety: Some(<Token![unsafe]>::default()),
+        abi: None,
+        fn_token: <Token![fn]>::default(),
+        ident: item.ident.clone(),
+        generics: Generics::default(),
+        paren_token: Default::default(),
+        inputs: vec![FnArg::Receiver(Receiver {
+            attrs: Vec::new(),
+            reference: Some((<Token![&]>::default(), None)),
+            mutability: None,
+            self_token: <Token![self]>::default(),
+        })]
+        .into_iter()
+        .collect(),
+        variadic: None,
+        output: ReturnType::Type(
+            <Token![->]>::default(),
+            Box::new(return_type),
+        ),
+    };
+
+    let summary = format!(
+        " The `{}` static from the loaded library.",
+        extern_static.symbol
+    );
+
+    ImplItemMethod {
+        attrs: wrapper_attributes(&item.attrs, &summary),
+        vis: visibility(config),
+        defaultness: None,
+        sig,
+        block: Block {
+            brace_token: Default::default(),
+            stmts: vec![Stmt::Expr(body)],
+        },
+    }
+}
+
+fn bindings_methods(bindings: &Bindings, config: &Config) -> ItemImpl {
+    let mut methods = Vec::new();
+
+    for func in &bindings.functions {
+        methods.push(defer_to_function(func, config).into());
+
+        if let (true, Some(status_codes)) =
+            (func.status_code, &config.status_codes)
+        {
+            methods.push(checked_method(func, config, status_codes).into());
+        }
+
+        if func.out_parameters > 0 {
+            methods.push(out_parameters_method(func, config).into());
+        }
+    }
+
+    for extern_static in &bindings.statics {
+        methods.push(static_accessor(extern_static, config).into());
+    }
+
+    ItemImpl {
+        attrs: vec![allow_lint("non_snake_case")],
+        brace_token: Default::default(),
+        defaultness: None,
+        generics: Generics::default(),
+        self_ty: Box::new(Type::Path(TypePath {
+            path: short_path(&config.struct_name),
+            qself: None,
+        })),
+        trait_: None,
+        unsafety: None,
+        impl_token: Default::default(),
+        items: methods,
+    }
+}
+
+/// The name of a method generated alongside a function's wrapper (e.g.
+/// `open_checked`).
+fn suffixed_method_name(func: &ExternFunction, suffix: &str) -> Ident {
+    let name = func.item.sig.ident.to_string();
+    Ident::new(
+        &format!("{}_{}", name.trim_start_matches("r#"), suffix),
+        Span::call_site(),
+    )
+}
+
+/// The methods we generate for a function in addition to its wrapper.
+fn extra_method_names(func: &ExternFunction, config: &Config) -> Vec<Ident> {
+    let mut names = Vec::new();
+
+    if config.emit_wrappers {
+        if func.status_code {
+            names.push(suffixed_method_name(func, "checked"));
+        }
+        if func.out_parameters > 0 {
+            names.push(suffixed_method_name(func, "out"));
+        }
+    }
+
+    names
+}
+
+/// Generate a method which calls the wrapper for a function returning a
+/// status code, turning anything non-zero into an error.
+///
+/// ```rust,ignore
+/// pub unsafe fn open_checked(&self, path: *const c_char) -> Result<(), Error> {
+///     match self.open(path) {
+///         0 => Ok(()),
+///         __status => Err(Error::from_status(__status)),
+///     }
+/// }
+/// ```
+fn checked_method(
+    extern_fn: &ExternFunction,
+    config: &Config,
+    status_codes: &StatusCodes,
+) -> ImplItemMethod {
+    let mut method = defer_to_function(extern_fn, config);
+    let name = &method.sig.ident;
+    let arguments: Vec<_> = normalised_arguments(&extern_fn.item.sig)
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect();
+
+    // these were checked before we started generating code
+    let error: Type = syn::parse_str(&status_codes.error_type).unwrap();
+    let conversion: Path = syn::parse_str(&status_codes.conversion).unwrap();
+
+    let check = quote::quote! {
+        match __status {
+            0 => Ok(()),
+            __status => Err(#conversion(__status)),
+        }
+    };
+
+    let (output, block): (Type, Block) = if extern_fn.optional {
+        (
+            syn::parse_quote!(Option<Result<(), #error>>),
+            syn::parse_quote! {{
+                self.#name(#(#arguments),*).map(|__status| #check)
+            }},
+        )
+    } else {
+        (
+            syn::parse_quote!(Result<(), #error>),
+            syn::parse_quote! {{
+                let __status = self.#name(#(#arguments),*);
+                #check
+            }},
+        )
+    };
+
+    let summary = format!(
+        " Calls [`{}::{}()`], turning a non-zero status code into an error.",
+        config.struct_name, name
+    );
+
+    method.attrs = vec![doc_comment(&summary)];
+    method.sig.ident = suffixed_method_name(extern_fn, "checked");
+    method.sig.output = ReturnType::Type(Default::default(), Box::new(output));
+    method.block = block;
+
+    method
+}
+
+/// Generate a method which passes uninitialised values to a function's
+/// out-parameters and returns them after the call.
+///
+/// ```rust,ignore
+/// pub unsafe fn get_size_out(&self, handle: *mut handle) -> (c_int, usize) {
+///     let mut size = ::std::mem::MaybeUninit::<usize>::uninit();
+///     let __result = self.get_size(handle, size.as_mut_ptr());
+///     (__result, size.assume_init())
+/// }
+/// ```
+///
+/// Functions which return a status code return `Result<usize, Error>`
+/// instead, and their out-parameters are only read when the call succeeds.
+fn out_parameters_method(
+    extern_fn: &ExternFunction,
+    config: &Config,
+) -> ImplItemMethod {
+    let mut method = defer_to_function(extern_fn, config);
+    let name = method.sig.ident.clone();
+    let arguments = normalised_arguments(&extern_fn.item.sig);
+    let (inputs, outputs) =
+        arguments.split_at(arguments.len() - extern_fn.out_parameters);
+
+    let input_names = inputs.iter().map(|(name, _)| name);
+    let output_names: Vec<_> = outputs.iter().map(|(name, _)| name).collect();
+    let output_types: Vec<_> = outputs
+        .iter()
+        .map(|(_, arg)| {
+            out_parameter_type(&FnArg::Typed((*arg).clone()))
+                .cloned()
+                .expect("checked by check_signature()")
+        })
+        .collect();
+
+    // nothing says all-zeroes is a valid T, so the values are only
+    // assumed to be initialised once the function has written to them
+    let initialised: Vec<Expr> = output_names
+        .iter()
+        .map(|name| syn::parse_quote!(#name.assume_init()))
+        .collect();
+    let (values, value_types): (Expr, Type) = match outputs {
+        [_] => (initialised[0].clone(), output_types[0].clone()),
+        _ => (
+            syn::parse_quote!((#(#initialised),*)),
+            syn::parse_quote!((#(#output_types),*)),
+        ),
+    };
+
+    // optional functions bail out early when the symbol is missing
+    let question = if extern_fn.optional {
+        quote::quote!(?)
+    } else {
+        TokenStream::new()
+    };
+    let call = quote::quote! {
+        self.#name(#(#input_names,)* #(#output_names.as_mut_ptr()),*) #question
+    };
+
+    let (result, output): (TokenStream, Type) =
+        match (&config.status_codes, &extern_fn.item.sig.output) {
+            (Some(status_codes), _) if extern_fn.status_code => {
+                let error: Type =
+                    syn::parse_str(&status_codes.error_type).unwrap();
+                let conversion: Path =
+                    syn::parse_str(&status_codes.conversion).unwrap();
+
+                (
+                    quote::quote! {
+                        let __status = #call;
+                        match __status {
+                            0 => Ok(#values),
+                            __status => Err(#conversion(__status)),
+                        }
+                    },
+                    syn::parse_quote!(Result<#value_types, #error>),
+                )
+            },
+            (_, ReturnType::Type(_, return_type)) => (
+                quote::quote! {
+                    let __result = #call;
+                    (__result, #(#initialised),*)
+                },
+                syn::parse_quote!((#return_type, #(#output_types),*)),
+            ),
+            (_, ReturnType::Default) => (
+                quote::quote! {
+                    #call;
+                    #values
+                },
+                value_types,
+            ),
+        };
+
+    let (result, output) = if extern_fn.optional {
+        (quote::quote!(Some({ #result })), option_of(output))
+    } else {
+        (result, output)
+    };
+
+    let summary = format!(
+        " Calls [`{}::{}()`], returning the values written to its out-parameters.",
+        config.struct_name, name
+    );
+
+    method.attrs = vec![doc_comment(&summary)];
+    method.sig.ident = suffixed_method_name(extern_fn, "out");
+    method.sig.inputs = method
+        .sig
+        .inputs
+        .into_iter()
+        .take(inputs.len() + 1)
+        .collect();
+    method.sig.output = ReturnType::Type(Default::default(), Box::new(output));
+    method.block = syn::parse_quote! {{
+        #(let mut #output_names = ::std::mem::MaybeUninit::<#output_types>::uninit();)*
+        #result
+    }};
+
+    method
+}
+
+/// The name of the error returned by the `load()` constructor.
+fn load_error_name(config: &Config) -> Ident {
+    Ident::new(
+        &format!("{}LoadError", config.struct_name),
+        Span::call_site(),
+    )
+}
+
+/// The error type returned when `load()` can't find a usable library.
+///
+/// This is all boilerplate, so it's much easier to read as a template.
+fn load_error(config: &Config) -> Vec<Item> {
+    let vis = visibility(config);
+    let name = load_error_name(config);
+    let doc = LitStr::new(
+        &format!(
+            " The error returned when [`{}::{}()`] can't find the library.",
+            config.struct_name, config.constructor_names.load
+        ),
+        Span::call_site(),
+    );
+    vec![
+        syn::parse_quote! {
+            #[doc = #doc]
+            #[derive(Debug)]
+            #vis struct #name {
+                /// Each path that was tried and the reason it couldn't be used.
+                pub attempts: Vec<(::std::path::PathBuf, ::libloading::Error)>,
+            }
+        },
+        syn::parse_quote! {
+            impl ::std::fmt::Display for #name {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    write!(f, "Unable to load the library")?;
+
+                    if self.attempts.is_empty() {
+                        return write!(f, " (no file names were provided)");
+                    }
+
+                    for (i, (path, error)) in self.attempts.iter().enumerate() {
+                        let separator = if i == 0 { ", tried" } else { "," };
+                        write!(f, "{} \"{}\" ({})", separator, path.display(), error)?;
+                    }
+
+                    Ok(())
+                }
+            }
+        },
+        syn::parse_quote! {
+            impl ::std::error::Error for #name {}
+        },
+    ]
+}
+
+/// A constructor which looks for the library in each of the places mentioned
+/// by the [`LibrarySearch`], stopping at the first one that works.
+fn load(search: &LibrarySearch, config: &Config) -> ImplItemMethod {
+    let vis = visibility(config);
+    let name = Ident::new(&config.constructor_names.load, Span::call_site());
+    let error = load_error_name(config);
+    let from_library =
+        Ident::new(&config.constructor_names.from_library, Span::call_site());
+    let LibrarySearch {
+        file_names,
+        directories,
+        env_vars,
+    } = search;
+
+    syn::parse_quote! {
+        /// Look for the library in each of the places it might be, using the
+        /// first one which can be loaded.
+        #vis unsafe fn #name() -> Result<Self, #error> {
+            const FILE_NAMES: &[&str] = &[#(#file_names),*];
+            const DIRECTORIES: &[&str] = &[#(#directories),*];
+            const ENV_VARS: &[&str] = &[#(#env_vars),*];
+
+            let mut candidates = Vec::new();
+
+            for variable in ENV_VARS {
+                if let Some(value) = ::std::env::var_os(variable) {
+                    let path = ::std::path::PathBuf::from(value);
+
+                    if path.is_dir() {
+                        candidates.extend(FILE_NAMES.iter().map(|name| path.join(name)));
+                    } else {
+                        candidates.push(path);
+                    }
+                }
+            }
+
+            for directory in DIRECTORIES {
+                let directory = ::std::path::Path::new(directory);
+                candidates.extend(FILE_NAMES.iter().map(|name| directory.join(name)));
+            }
+
+            // let the system's dynamic loader search its usual locations
+            candidates.extend(FILE_NAMES.iter().map(::std::path::PathBuf::from));
+
+            let mut attempts = Vec::new();
+
+            for candidate in candidates {
+                match ::libloading::Library::new(&candidate).and_then(|library| Self::#from_library(library)) {
+                    Ok(bindings) => return Ok(bindings),
+                    Err(e) => attempts.push((candidate, e)),
+                }
+            }
+
+            Err(#error { attempts })
+        }
+    }
+}
+
+/// The `static` holding the process-wide instance of our bindings.
+fn global_instance_name(config: &Config) -> Ident {
+    Ident::new(
+        &format!("__{}_GLOBAL", config.struct_name.to_uppercase()),
+        Span::call_site(),
+    )
+}
+
+/// A process-wide instance of the bindings, plus functions for initialising
+/// and accessing it.
+fn global_instance(config: &Config) -> Vec<Item> {
+    let vis = visibility(config);
+    let struct_name = Ident::new(&config.struct_name, Span::call_site());
+    let global = global_instance_name(config);
+
+    let mut methods: ItemImpl = syn::parse_quote! {
+        impl #struct_name {
+            /// Get the process-wide instance of these bindings, if it has been
+            /// set.
+            #vis fn global() -> Option<&'static Self> { #global.get() }
+
+            /// Use these bindings as the process-wide instance, giving them
+            /// back if it was already set.
+            #vis fn set_global(self) -> Result<&'static Self, Self> {
+                #global.set(self)?;
+                Ok(#global.get().expect("The global instance was just set"))
+            }
+        }
+    };
+
+    if config.library_search.is_some() {
+        let load =
+            Ident::new(&config.constructor_names.load, Span::call_site());
+        let error = load_error_name(config);
+
+        methods.items.push(syn::parse_quote! {
+            /// Get the process-wide instance of these bindings, loading the
+            /// library if this is the first time it has been used.
+            ///
+            /// If several threads try to load the library at the same time,
+            /// only one of the instances is kept.
+            #vis unsafe fn load_global() -> Result<&'static Self, #error> {
+                if let Some(bindings) = #global.get() {
+                    return Ok(bindings);
+                }
+
+                let _ = #global.set(Self::#load()?);
+                Ok(#global.get().expect("The global instance was just set"))
+            }
+        });
+    }
+
+    vec![
+        syn::parse_quote! {
+            #[allow(non_upper_case_globals)]
+            static #global: ::std::sync::OnceLock<#struct_name> = ::std::sync::OnceLock::new();
+        },
+        methods.into(),
+    ]
+}
+
+/// The name of the struct returned by the generated `stats()` method.
+fn call_stats_name(config: &Config) -> Ident {
+    Ident::new(
+        &format!("{}CallStats", config.struct_name),
+        Span::call_site(),
+    )
+}
+
+/// The functions we keep statistics for. Variadic functions are called via
+/// a function pointer, so we never see them being called.
+fn recorded_functions(
+    bindings: &Bindings,
+) -> impl Iterator<Item = &ExternFunction> {
+    bindings
+        .functions
+        .iter()
+        .filter(|func| func.item.sig.variadic.is_none())
+}
+
+/// Initialise the counters used when [`Config::call_stats`] is set.
+fn new_stats(bindings: &Bindings) -> FieldValue {
+    let names = recorded_functions(bindings)
+        .map(|func| LitStr::new(&func.symbol, Span::call_site()));
+
+    syn::parse_quote! {
+        _stats: [#(#names),*]
+            .iter()
+            .map(|&name| (name, Default::default()))
+            .collect()
+    }
+}
+
+/// Count the call and add its duration to the running total.
+fn recorded_call(
+    extern_fn: &ExternFunction,
+    call: Expr,
+    output: &ReturnType,
+) -> Expr {
+    let name = LitStr::new(&extern_fn.symbol, Span::call_site());
+    let count: Stmt = syn::parse_quote! {
+        self._stats[#name].0.fetch_add(1, ::std::sync::atomic::Ordering::Relaxed);
+    };
+
+    if returns_never(output) {
+        return syn::parse_quote!({ #count #call });
+    }
+
+    // counting after the call means optional functions that weren't found
+    // are ignored
+    syn::parse_quote! {{
+        let __start = ::std::time::Instant::now();
+        let __result = #call;
+        #count
+        self._stats[#name].1.fetch_add(
+            __start.elapsed().as_nanos() as u64,
+            ::std::sync::atomic::Ordering::Relaxed,
+        );
+        __result
+    }}
+}
+
+/// The `stats()` method and the type it returns.
+fn call_stats(config: &Config) -> Vec<Item> {
+    let vis = visibility(config);
+    let struct_name = Ident::new(&config.struct_name, Span::call_site());
+    let stats = call_stats_name(config);
+
+    vec![
+        syn::parse_quote! {
+            /// How many times a function was called and the total time spent
+            /// in it.
+            #[derive(Debug, Default, Copy, Clone, PartialEq)]
+            #vis struct #stats {
+                pub calls: u64,
+                pub total_duration: ::std::time::Duration,
+            }
+        },
+        syn::parse_quote! {
+            impl #struct_name {
+                /// Get the statistics for each function, keyed by the
+                /// function's name in the library.
+                #vis fn stats(&self) -> ::std::collections::HashMap<&'static str, #stats> {
+                    self._stats
+                        .iter()
+                        .map(|(&name, (calls, nanos))| {
+                            let stats = #stats {
+                                calls: calls.load(::std::sync::atomic::Ordering::Relaxed),
+                                total_duration: ::std::time::Duration::from_nanos(
+                                    nanos.load(::std::sync::atomic::Ordering::Relaxed),
+                                ),
+                            };
+                            (name, stats)
+                        })
+                        .collect()
+                }
+            }
+        },
+    ]
+}
+
+/// Log the arguments a function is called with and its return value.
+///
+/// We can't tell whether an arbitrary type implements `Debug`, so anything we
+/// don't recognise (see [`is_debug()`]) is logged as `..`.
+fn traced_call(
+    tracing: CallTracing,
+    extern_fn: &ExternFunction,
+    arguments: &[(Ident, &PatType)],
+    call: Expr,
+    output: &ReturnType,
+) -> Vec<Stmt> {
+    let trace: Path = match tracing {
+        CallTracing::Log => syn::parse_quote!(::log::trace),
+        CallTracing::Tracing => syn::parse_quote!(::tracing::trace),
+    };
+
+    let mut format_args = Vec::new();
+    let mut values = Vec::new();
+
+    for (name, input) in arguments {
+        let label = name.to_string();
+        let label = label.trim_start_matches("r#");
+
+        if is_debug(&input.ty) {
+            format_args.push(format!("{} = {{:?}}", label));
+            values.push(name);
+        } else {
+            format_args.push(format!("{} = ..", label));
+        }
+    }
+
+    let calling = LitStr::new(
+        &format!("Calling {}({})", extern_fn.symbol, format_args.join(", ")),
+        Span::call_site(),
+    );
+
+    let mut stmts: Vec<Stmt> =
+        vec![syn::parse_quote!(#trace!(#calling #(, #values)*);)];
+
+    // there's nothing to log if the function never returns
+    if returns_never(output) {
+        stmts.push(Stmt::Expr(call));
+        return stmts;
+    }
+
+    let debug_output = match output {
+        ReturnType::Type(_, ty) => is_debug(ty),
+        ReturnType::Default => true,
+    };
+
+    stmts.push(syn::parse_quote!(let __result = #call;));
+
+    if debug_output {
+        let returned = LitStr::new(
+            &format!("{}() returned {{:?}}", extern_fn.symbol),
+            Span::call_site(),
+        );
+        stmts.push(syn::parse_quote!(#trace!(#returned, __result);));
+    } else {
+        let returned = LitStr::new(
+            &format!("{}() returned", extern_fn.symbol),
+            Span::call_site(),
+        );
+        stmts.push(syn::parse_quote!(#trace!(#returned);));
+    }
+
+    stmts.push(Stmt::Expr(syn::parse_quote!(__result)));
+
+    stmts
+}
+
+/// Do we know this type implements `Debug` just by looking at it?
+fn is_debug(ty: &Type) -> bool {
+    const PRIMITIVES: &[&str] = &[
+        "c_char",
+        "c_schar",
+        "c_uchar",
+        "c_short",
+        "c_ushort",
+        "c_int",
+        "c_uint",
+        "c_long",
+        "c_ulong",
+        "c_longlong",
+        "c_ulonglong",
+        "c_float",
+        "c_double",
+        "i8",
+        "u8",
+        "i16",
+        "u16",
+        "i32",
+        "u32",
+        "i64",
+        "u64",
+        "i128",
+        "u128",
+        "isize",
+        "usize",
+        "f32",
+        "f64",
+        "bool",
+    ];
+
+    match ty {
+        Type::Ptr(_) | Type::BareFn(_) => true,
+        Type::Tuple(tuple) => tuple.elems.iter().all(is_debug),
+        Type::Paren(paren) => is_debug(&paren.elem),
+        Type::Path(TypePath { path, qself: None }) => {
+            let last = match path.segments.last() {
+                Some(segment) => segment,
+                None => return false,
+            };
+
+            match &last.arguments {
+                PathArguments::None => {
+                    PRIMITIVES.iter().any(|primitive| last.ident == primitive)
+                },
+                // bindgen uses Option<...> for nullable function pointers
+                PathArguments::AngleBracketed(args)
+                    if last.ident == "Option" =>
+                {
+                    args.args.iter().all(|arg| match arg {
+                        GenericArgument::Type(ty) => is_debug(ty),
+                        _ => false,
+                    })
+                },
+                _ => false,
+            }
+        },
+        _ => false,
+    }
+}
+
+/// `_lock: ::std::sync::Mutex::new(())`
+fn new_lock() -> FieldValue {
+    syn::parse_quote!(_lock: ::std::sync::Mutex::new(()))
+}
+
+/// Hold the lock until the end of the current scope. A poisoned lock is
+/// fine because the mutex doesn't protect any data.
+fn acquire_lock(receiver: TokenStream) -> Stmt {
+    syn::parse_quote! {
+        let __guard = #receiver._lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    }
+}
+
+/// `unsafe impl Send` and `Sync`, if the user told us it's okay.
+fn thread_safety_impls(config: &Config) -> Vec<Item> {
+    let justification = match &config.thread_safety {
+        ThreadSafety::Unspecified => return Vec::new(),
+        ThreadSafety::ThreadSafe { justification } => justification.clone(),
+        ThreadSafety::Serialised => String::from(
+            "Every function call is serialised by the `_lock` mutex.",
+        ),
+    };
+    let doc =
+        LitStr::new(&format!(" Safety: {}", justification), Span::call_site());
+    let struct_name = Ident::new(&config.struct_name, Span::call_site());
+
+    vec![
+        syn::parse_quote! {
+            #[doc = #doc]
+            unsafe impl Send for #struct_name {}
+        },
+        syn::parse_quote! {
+            #[doc = #doc]
+            unsafe impl Sync for #struct_name {}
+        },
+    ]
+}
+
+/// The error returned by free functions when [`WhenUnloaded::ReturnError`]
+/// is used.
+fn unavailable_error_name(config: &Config) -> Ident {
+    Ident::new(
+        &format!("{}Unavailable", config.struct_name),
+        Span::call_site(),
+    )
+}
+
+fn unavailable_error(config: &Config) -> Vec<Item> {
+    let vis = visibility(config);
+    let name = unavailable_error_name(config);
+
+    vec![
+        syn::parse_quote! {
+            /// A function was called before the library was loaded, or the
+            /// library doesn't provide it.
+            #[derive(Debug, Copy, Clone, PartialEq)]
+            #vis struct #name {
+                /// The function being called.
+                pub function: &'static str,
+            }
+        },
+        syn::parse_quote! {
+            impl ::std::fmt::Display for #name {
+                fn fmt(&self, f: &mut ::std::fmt::Formatter<'_>) -> ::std::fmt::Result {
+                    write!(
+                        f,
+                        "Unable to call \"{}\" because the library hasn't been loaded or doesn't provide it",
+                        self.function
+                    )
+                }
+            }
+        },
+        syn::parse_quote! {
+            impl ::std::error::Error for #name {}
+        },
+    ]
+}
+
+/// A module containing a free function for each of the original `extern`
+/// functions, which dispatch to the global instance.
+fn free_functions_module(
+    bindings: &Bindings,
+    config: &Config,
+    free_functions: &FreeFunctions,
+) -> Item {
+    let vis = visibility(config);
+    let module = Ident::new(&free_functions.module, Span::call_site());
+    let doc = LitStr::new(
+        &format!(
+            " Free functions which call the global [`{}`] instance.",
+            config.struct_name
+        ),
+        Span::call_site(),
+    );
+    let functions = bindings
+        .functions
+        .iter()
+        .map(|func| free_function(func, config, free_functions.when_unloaded));
+
+    syn::parse_quote! {
+        #[doc = #doc]
+        #vis mod #module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #(#functions)*
+        }
+    }
+}
+
+fn free_function(
+    extern_fn: &ExternFunction,
+    config: &Config,
+    when_unloaded: WhenUnloaded,
+) -> ItemFn {
+    let struct_name = Ident::new(&config.struct_name, Span::call_site());
+    let name = &extern_fn.item.sig.ident;
+    let function = LitStr::new(&extern_fn.symbol, Span::call_site());
+    let arguments = normalised_arguments(&extern_fn.item.sig);
+    let argument_names: Vec<_> =
+        arguments.iter().map(|(name, _)| name.clone()).collect();
+
+    // `Result<!, _>` isn't stable, so functions which never return keep
+    // their original signature and panic instead
+    let when_unloaded = if returns_never(&extern_fn.item.sig.output) {
+        WhenUnloaded::Panic
+    } else {
+        when_unloaded
+    };
+
+    let (mut sig, call) = if extern_fn.item.sig.variadic.is_some() {
+        // we can't define variadic functions, so hand out the pointer
+        let mut sig = function_pointer_getter(extern_fn, config).sig;
+        sig.inputs.clear();
+        (sig, quote::quote!(__bindings.#name))
+    } else {
+        let mut sig = defer_to_function(extern_fn, config).sig;
+        sig.inputs = sig
+            .inputs
+            .into_iter()
+            .filter(|arg| matches!(arg, FnArg::Typed(_)))
+            .collect();
+        sig.output = extern_fn.item.sig.output.clone();
+
+        let unwrap_optional = |value: TokenStream| match when_unloaded {
+            WhenUnloaded::Panic => quote::quote! {
+                #value.expect(concat!("The \"", #function, "\" function isn't available"))
+            },
+            WhenUnloaded::ReturnError => {
+                let error = unavailable_error_name(config);
+                quote::quote!(#value.ok_or(#error { function: #function })?)
+            },
+        };
+
+        let call = if config.emit_wrappers {
+            // go through the wrapper so calls are still serialised, traced
+            // and counted
+            let call = quote::quote!(__bindings.#name(#(#argument_names),*));
+
+            if extern_fn.optional {
+                unwrap_optional(call)
+            } else {
+                call
+            }
+        } else {
+            let field = quote::quote!(__bindings.#name);
+            let function_pointer = if extern_fn.optional {
+                unwrap_optional(field)
+            } else {
+                field
+            };
+
+            quote::quote!((#function_pointer)(#(#argument_names),*))
+        };
+
+        (sig, call)
+    };
+
+    let block: Block = match when_unloaded {
+        WhenUnloaded::Panic => syn::parse_quote! {{
+            let __bindings = #struct_name::global()
+                .expect(concat!("The global ", stringify!(#struct_name), " instance hasn't been set"));
+            #call
+        }},
+        WhenUnloaded::ReturnError => {
+            let error = unavailable_error_name(config);
+            let return_type = match &sig.output {
+                ReturnType::Default => Type::Tuple(TypeTuple {
+                    paren_token: Default::default(),
+                    elems: Default::default(),
+                }),
+                ReturnType::Type(_, ty) => (**ty).clone(),
+            };
+            sig.output = syn::parse_quote!(-> Result<#return_type, #error>);
+
+            syn::parse_quote! {{
+                let __bindings = #struct_name::global()
+                    .ok_or(#error { function: #function })?;
+                Ok(#call)
+            }}
+        },
+    };
+
+    // free functions live in their own module, so they can keep the C name
+    sig.ident = extern_fn.original_name.clone();
+
+    ItemFn {
+        attrs: wrapper_attributes(
+            &extern_fn.item.attrs,
+            &function_summary(extern_fn),
+        ),
+        vis: nested_visibility(config),
+        sig,
+        block: Box::new(block),
+    }
+}
+
+/// `#[cfg(feature = "...")]` or `#[cfg(not(feature = "..."))]`.
+fn feature_cfg(feature: &str, enabled: bool) -> Attribute {
+    let feature = LitStr::new(feature, Span::call_site());
+
+    Attribute {
+        path: short_path("cfg"),
+        tokens: if enabled {
+            quote::quote!((feature = #feature))
+        } else {
+            quote::quote!((not(feature = #feature)))
+        },
+        style: AttrStyle::Outer,
+        pound_token: Default::default(),
+        bracket_token: Default::default(),
+    }
+}
+
+/// The version of our vtable used when the library is linked normally, which
+/// doesn't need to hold onto a library handle.
+fn linked_vtable(
+    bindings: &Bindings,
+    config: &Config,
+    feature: &str,
+) -> ItemStruct {
+    let mut vtable = bindings_vtable(bindings, config);
+
+    if let Fields::Named(fields) = &mut vtable.fields {
+        fields.named = std::mem::take(&mut fields.named)
+            .into_iter()
+            .filter(|field| {
+                !matches!(&field.ident, Some(name) if name == "_library")
+            })
+            .collect();
+    }
+    vtable.attrs.push(feature_cfg(feature, true));
+
+    vtable
+}
+
+/// Constructors with the same signatures as normal, except they ignore the
+/// library and use the symbols we were linked against.
+fn linked_constructor(
+    bindings: &Bindings,
+    config: &Config,
+    feature: &str,
+) -> ItemImpl {
+    let mut constructor = bindings_constructor(bindings, config);
+
+    for item in &mut constructor.items {
+        if let ImplItem::Method(method) = item {
+            method.block = linked_constructor_body(bindings, config);
+        }
+    }
+
+    constructor.attrs.push(feature_cfg(feature, true));
+    // the constructors' arguments are deliberately ignored
+    constructor.attrs.push(allow_lint("unused_variables"));
+
+    constructor
+}
+
+fn linked_constructor_body(bindings: &Bindings, config: &Config) -> Block {
+    let mut stmts = Vec::new();
+    let mut fields = Vec::new();
+
+    if config.thread_safety == ThreadSafety::Serialised {
+        fields.push(new_lock());
+    }
+
+    if config.call_stats {
+        fields.push(new_stats(bindings));
+    }
+
+    for func in &bindings.functions {
+        let declaration = ForeignItemFn {
+            attrs: vec![link_name(&func.link_name)],
+            vis: Visibility::Inherited,
+            ..func.item.clone()
+        };
+        stmts.push(Stmt::Item(extern_block(
+            func.abi.clone(),
+            ForeignItem::Fn(declaration),
+        )));
+
+        let name = Expr::Path(ExprPath {
+            path: Path::from(func.item.sig.ident.clone()),
+            attrs: Vec::new(),
+            qself: None,
+        });
+        let value = if func.optional {
+            Expr::Call(ExprCall {
+                func: Box::new(Expr::Path(ExprPath {
+                    path: short_path("Some"),
+                    attrs: Vec::new(),
+                    qself: None,
+                })),
+                args: vec![name].into_iter().collect(),
+                attrs: Vec::new(),
+                paren_token: Default::default(),
+            })
+        } else {
+            name
+        };
+
+        fields.push(FieldValue {
+            attrs: Vec::new(),
+            member: Member::Named(func.item.sig.ident.clone()),
+            colon_token: Some(<Token![:]>::default()),
+            expr: value,
+        });
+    }
+
+    for extern_static in &bindings.statics {
+        let item = &extern_static.item;
+        let declaration = ForeignItemStatic {
+            attrs: vec![link_name(&extern_static.link_name)],
+            vis: Visibility::Inherited,
+            ..item.clone()
+        };
+        stmts.push(Stmt::Item(extern_block(
+            LitStr::new("C", Span::call_site()),
+            ForeignItem::Static(declaration),
+        )));
+
+        // ::std::ptr::addr_of!(NAME) or ::std::ptr::addr_of_mut!(NAME)
+        let macro_name = match item.mutability {
+            Some(_) => "addr_of_mut",
+            None => "addr_of",
+        };
+        fields.push(FieldValue {
+            attrs: Vec::new(),
+            member: Member::Named(item.ident.clone()),
+            colon_token: Some(<Token![:]>::default()),
+            expr: static_address_of(
+                Expr::Macro(ExprMacro {
+                    attrs: Vec::new(),
+                    mac: Macro {
+                        path: Path {
+                            leading_colon: Some(<Token![::]>::default()),
+                            ..long_path(["std", "ptr", macro_name])
+                        },
+                        bang_token: <Token![!]>::default(),
+                        delimiter: MacroDelimiter::Paren(Default::default()),
+                        tokens: item.ident.to_token_stream(),
+                    },
+                }),
+                config,
+            ),
+        });
+    }
+
+    let struct_literal = Expr::Struct(ExprStruct {
+        path: short_path(&config.struct_name),
+        fields: fields.into_iter().collect(),
+        brace_token: Default::default(),
+        dot2_token: None,
+        rest: None,
+        attrs: Vec::new(),
+    });
+    stmts.push(Stmt::Expr(Expr::Call(ExprCall {
+        func: Box::new(Expr::Path(ExprPath {
+            path: short_path("Ok"),
+            attrs: Vec::new(),
+            qself: None,
+        })),
+        args: vec![struct_literal].into_iter().collect(),
+        paren_token: Default::default(),
+        attrs: Vec::new(),
+    })));
+
+    Block {
+        brace_token: Default::default(),
+        stmts,
+    }
+}
+
+/// `#[link_name = "..."]`
+fn link_name(symbol: &str) -> Attribute {
+    Attribute {
+        path: short_path("link_name"),
+        tokens: doc_tokens(symbol),
+        style: AttrStyle::Outer,
+        pound_token: Default::default(),
+        bracket_token: Default::default(),
+    }
+}
+
+/// `extern "ABI" { ... }` with a single item.
+fn extern_block(abi: LitStr, item: ForeignItem) -> Item {
+    Item::ForeignMod(ItemForeignMod {
+        attrs: Vec::new(),
+        abi: Abi {
+            extern_token: <Token![extern]>::default(),
+            name: Some(abi),
+        },
+        brace_token: Default::default(),
+        items: vec![item],
+    })
+}
+
+/// The name of the trait generated when [`Config::emit_trait`] is set.
+fn api_trait_name(config: &Config) -> String {
+    format!("{}Api", config.struct_name)
+}
+
+/// A trait with one method for each function, so code can be written against
+/// the library's API without caring whether it was actually loaded.
+fn api_trait(bindings: &Bindings, config: &Config) -> ItemTrait {
+    let items = bindings
+        .functions
+        .iter()
+        .map(|func| {
+            let ImplItemMethod { attrs, sig, .. } =
+                defer_to_function(func, config);
+
+            TraitItem::Method(TraitItemMethod {
+                attrs,
+                sig,
+                default: None,
+                semi_token: Some(<Token![;]>::default()),
+            })
+        })
+        .collect();
+
+    ItemTrait {
+        attrs: vec![doc_comment(&format!(
+            " The functions provided by [`{}`].",
+            config.struct_name
+        ))],
+        vis: visibility(config),
+        unsafety: None,
+        auto_token: None,
+        trait_token: <Token![trait]>::default(),
+        ident: Ident::new(&api_trait_name(config), Span::call_site()),
+        generics: Generics::default(),
+        colon_token: None,
+        supertraits: Punctuated::new(),
+        brace_token: Default::default(),
+        items,
+    }
+}
+
+fn api_impl(bindings: &Bindings, config: &Config) -> ItemImpl {
+    let items = bindings
+        .functions
+        .iter()
+        .map(|func| {
+            // the docs and lints live on the trait's declaration
+            ImplItem::Method(ImplItemMethod {
+                attrs: Vec::new(),
+                vis: Visibility::Inherited,
+                ..defer_to_function(func, config)
+            })
+        })
+        .collect();
+
+    ItemImpl {
+        attrs: Vec::new(),
+        defaultness: None,
+        unsafety: None,
+        impl_token: Default::default(),
+        generics: Generics::default(),
+        trait_: Some((
+            None,
+            short_path(api_trait_name(config)),
+            <Token![for]>::default(),
+        )),
+        self_ty: Box::new(Type::Path(TypePath {
+            path: short_path(&config.struct_name),
+            qself: None,
+        })),
+        brace_token: Default::default(),
+        items,
+    }
+}
+
+/// The name of the struct generated when [`Config::mock_cfg`] is set.
+fn mock_name(config: &Config) -> String {
+    format!("Mock{}", config.struct_name)
+}
+
+/// `#[cfg(...)]`
+fn cfg_attribute(predicate: &str) -> Attribute {
+    let predicate: Meta =
+        syn::parse_str(predicate).expect("The cfg was already validated");
+
+    Attribute {
+        path: short_path("cfg"),
+        tokens: Group::new(Delimiter::Parenthesis, predicate.to_token_stream())
+            .into_token_stream(),
+        style: AttrStyle::Outer,
+        pound_token: Default::default(),
+        bracket_token: Default::default(),
+    }
+}
+
+/// A fake version of the generated struct, where each function is backed by
+/// a closure.
+fn mock_struct(bindings: &Bindings, config: &Config, cfg: &str) -> ItemStruct {
+    let fields = bindings
+        .functions
+        .iter()
+        .map(|func| Field {
+            colon_token: Some(<Token![:]>::default()),
+            ident: Some(func.item.sig.ident.clone()),
+            ty: option_of(generic_of(
+                "Box",
+                mock_closure_type(&defer_to_function(func, config).sig),
+            )),
+            vis: visibility(config),
+            attrs: Vec::new(),
+        })
+        .collect();
+
+    ItemStruct {
+        attrs: vec![
+            doc_comment(&format!(
+                " A fake [`{}`] for use in tests, where each function calls a closure.",
+                config.struct_name
+            )),
+            doc_comment(""),
+            doc_comment(
+                " Calling a function which wasn't given a closure will panic.",
+            ),
+            cfg_attribute(cfg),
+            Attribute {
+                path: short_path("derive"),
+                tokens: Group::new(
+                    Delimiter::Parenthesis,
+                    Ident::new("Default", Span::call_site()).into_token_stream(),
+                )
+                .into_token_stream(),
+                style: AttrStyle::Outer,
+                pound_token: Default::default(),
+                bracket_token: Default::default(),
+            },
+        ],
+        vis: visibility(config),
+        struct_token: <Token![struct]>::default(),
+        ident: Ident::new(&mock_name(config), Span::call_site()),
+        generics: Generics::default(),
+        fields: Fields::Named(FieldsNamed {
+            brace_token: Brace::default(),
+            named: fields,
+        }),
+        semi_token: None,
+    }
+}
+
+/// Does a function have a return type of `!`?
+fn returns_never(output: &ReturnType) -> bool {
+    matches!(output, ReturnType::Type(_, ty) if matches!(**ty, Type::Never(_)))
+}
+
+/// The closure that will be called instead of a method, `dyn Fn(...) -> ...`.
+fn mock_closure_type(method: &Signature) -> Type {
+    let inputs = method
+        .inputs
+        .iter()
+        .filter_map(|arg| match arg {
+            FnArg::Typed(PatType { ty, .. }) => Some((**ty).clone()),
+            FnArg::Receiver(_) => None,
+        })
+        .collect();
+
+    let closure = Path::from(PathSegment {
+        ident: Ident::new("Fn", Span::call_site()),
+        arguments: PathArguments::Parenthesized(
+            ParenthesizedGenericArguments {
+                paren_token: Default::default(),
+                inputs,
+                // `dyn Fn() -> !` isn't stable, so the mock panics if the
+                // closure returns
+                output: if returns_never(&method.output) {
+                    ReturnType::Default
+                } else {
+                    method.output.clone()
+                },
+            },
+        ),
+    });
+
+    Type::TraitObject(TypeTraitObject {
+        dyn_token: Some(<Token![dyn]>::default()),
+        bounds: vec![TypeParamBound::Trait(TraitBound {
+            paren_token: None,
+            modifier: TraitBoundModifier::None,
+            lifetimes: None,
+            path: closure,
+        })]
+        .into_iter()
+        .collect(),
+    })
+}
+
+fn mock_impl(bindings: &Bindings, config: &Config, cfg: &str) -> ItemImpl {
+    let mock_name = mock_name(config);
+
+    let items = bindings
+        .functions
+        .iter()
+        .map(|func| {
+            let method = defer_to_function(func, config);
+            let name = &method.sig.ident;
+
+            let argument_names =
+                method.sig.inputs.iter().filter_map(|arg| match arg {
+                    FnArg::Typed(PatType { pat, .. }) => match &**pat {
+                        Pat::Ident(PatIdent { ident, .. }) => {
+                            Some(Expr::Path(ExprPath {
+                                path: Path::from(ident.clone()),
+                                qself: None,
+                                attrs: Vec::new(),
+                            }))
+                        },
+                        _ => unreachable!("Arguments are normalised"),
+                    },
+                    FnArg::Receiver(_) => None,
+                });
+
+            // self.function.as_ref().expect("...")(args)
+            let field = Expr::Field(ExprField {
+                base: Box::new(Expr::Path(ExprPath {
+                    path: short_path("self"),
+                    qself: None,
+                    attrs: Vec::new(),
+                })),
+                attrs: Vec::new(),
+                dot_token: Default::default(),
+                member: Member::Named(name.clone()),
+            });
+            let message = format!(
+                "{}::{}() was called without being given a closure",
+                mock_name, name
+            );
+            let closure = method_call(
+                method_call(field, "as_ref", None),
+                "expect",
+                Some(Expr::Lit(ExprLit {
+                    attrs: Vec::new(),
+                    lit: Lit::Str(LitStr::new(&message, Span::call_site())),
+                })),
+            );
+            let call = Expr::Call(ExprCall {
+                func: Box::new(closure),
+                args: argument_names.collect(),
+                attrs: Vec::new(),
+                paren_token: Default::default(),
+            });
+
+            let stmts = if returns_never(&method.sig.output) {
+                let message = format!(
+                    "The closure for {}::{}() returned, but the function never returns",
+                    mock_name, name
+                );
+                vec![
+                    Stmt::Semi(call, Default::default()),
+                    syn::parse_quote!(panic!(#message);),
+                ]
+            } else {
+                vec![Stmt::Expr(call)]
+            };
+
+            ImplItem::Method(ImplItemMethod {
+                attrs: Vec::new(),
+                vis: Visibility::Inherited,
+                block: Block {
+                    brace_token: Default::default(),
+                    stmts,
+                },
+                ..method
+            })
+        })
+        .collect();
+
+    ItemImpl {
+        attrs: vec![cfg_attribute(cfg)],
+        defaultness: None,
+        unsafety: None,
+        impl_token: Default::default(),
+        generics: Generics::default(),
+        trait_: Some((
+            None,
+            short_path(api_trait_name(config)),
+            <Token![for]>::default(),
+        )),
+        self_ty: Box::new(Type::Path(TypePath {
+            path: short_path(&mock_name),
+            qself: None,
+        })),
+        brace_token: Default::default(),
+        items,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BindingStrategy, ConstructorNames};
+    use syn::{File, ForeignItemFn};
+
+    struct Always;
+
+    impl BindingStrategy for Always {
+        fn should_include(&self, _item: &ForeignItemFn) -> bool { true }
+    }
+
+    fn extract(src: &str) -> Bindings {
+        let mut file: File = syn::parse_str(src).unwrap();
+        crate::bindings::extract_raw_bindings(
+            &mut file,
+            &Always,
+            &Config::default(),
+        )
+        .unwrap()
+    }
+
+    fn argument_names(item: ForeignItemFn) -> Vec<String> {
+        normalised_arguments(&item.sig)
+            .into_iter()
+            .map(|(name, _)| name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn named_arguments_are_left_alone() {
+        let item: ForeignItemFn = syn::parse_quote! {
+            pub fn add(left: c_int, right: c_int) -> c_int;
+        };
+
+        assert_eq!(argument_names(item), &["left", "right"]);
+    }
+
+    #[test]
+    fn unnamed_arguments_are_given_names() {
+        let item: ForeignItemFn = syn::parse_quote! {
+            pub fn add(_: c_int, right: c_int, _: c_int) -> c_int;
+        };
+
+        assert_eq!(argument_names(item), &["arg1", "right", "arg3"]);
+    }
+
+    #[test]
+    fn duplicate_and_clashing_names_are_made_unique() {
+        let item: ForeignItemFn = syn::parse_quote! {
+            pub fn add(arg2: c_int, arg2: c_int, mut r#type: c_int) -> c_int;
+        };
+
+        assert_eq!(argument_names(item), &["arg2", "arg2_", "r#type"]);
+    }
+
+    #[test]
+    fn reserved_names_are_an_error_by_default() {
+        let mut bindings = extract(
+            "extern \"C\" { pub fn load_from_path(path: *const c_char); }",
+        );
+
+        let got = resolve_name_collisions(&mut bindings, &Config::default());
+
+        assert!(
+            matches!(got, Err(Error::NameCollision { name }) if name == "load_from_path")
+        );
+    }
+
+    #[test]
+    fn duplicate_method_names_are_an_error() {
+        let mut bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+            }"#,
+        );
+        let duplicate = bindings.functions[0].clone();
+        bindings.functions.push(duplicate);
+
+        let got = resolve_name_collisions(&mut bindings, &Config::default());
+
+        assert!(
+            matches!(got, Err(Error::DuplicateName { name }) if name == "add")
+        );
+    }
+
+    #[test]
+    fn duplicate_raw_identifiers_can_be_renamed() {
+        let mut bindings = extract(
+            r#"extern "C" {
+                pub fn r#type() -> c_int;
+            }"#,
+        );
+        let duplicate = bindings.functions[0].clone();
+        bindings.functions.push(duplicate);
+        let config = Config {
+            name_collision_suffix: Some(String::from("_c")),
+            ..Default::default()
+        };
+
+        resolve_name_collisions(&mut bindings, &config).unwrap();
+
+        assert_eq!(bindings.functions[1].item.sig.ident, "type_c");
+    }
+
+    #[test]
+    fn reserved_names_can_be_renamed() {
+        let mut bindings = extract(
+            r#"extern "C" {
+                pub fn from_library(path: *const c_char);
+                pub fn from_library_c();
+                pub static _library: c_int;
+            }"#,
+        );
+        let config = Config {
+            name_collision_suffix: Some(String::from("_c")),
+            ..Default::default()
+        };
+
+        resolve_name_collisions(&mut bindings, &config).unwrap();
+
+        let from_library = &bindings.functions[0];
+        assert_eq!(from_library.item.sig.ident, "from_library_c_c");
+        assert_eq!(from_library.symbol, "from_library");
+        assert_eq!(bindings.functions[1].item.sig.ident, "from_library_c");
+        assert_eq!(bindings.statics[0].item.ident, "_library_c");
+        assert_eq!(bindings.statics[0].symbol, "_library");
+    }
+
+    #[test]
+    fn wrappers_keep_documentation() {
+        let item: ForeignItemFn = syn::parse_quote! {
+            #[doc = " Add two numbers."]
+            #[must_use]
+            #[link_name = "\u{1}_add"]
+            pub fn add(left: c_int, right: c_int) -> c_int;
+        };
+        let expected: Vec<Attribute> = vec![
+            syn::parse_quote!(#[doc = " Add two numbers."]),
+            syn::parse_quote!(#[doc = ""]),
+            syn::parse_quote!(#[doc = " Calls `_add`."]),
+            syn::parse_quote!(#[must_use]),
+        ];
+
+        let got = wrapper_attributes(&item.attrs, " Calls `_add`.");
+
+        assert_eq!(got, expected);
+    }
+
+    #[test]
+    fn the_api_trait_has_a_method_per_function() {
+        let bindings = extract(
+            r#"extern "C" {
+                #[doc = " Add two numbers."]
+                pub fn add(left: c_int, right: c_int) -> c_int;
+                pub static VERSION: *const c_char;
+            }"#,
+        );
+        let config = Config {
+            struct_name: String::from("Foo"),
+            ..Default::default()
+        };
+        let should_be: ItemTrait = syn::parse_quote! {
+            #[doc = " The functions provided by [`Foo`]."]
+            pub trait FooApi {
+                #[doc = " Add two numbers."]
+                #[doc = ""]
+                #[doc = " Calls the `add` function from the loaded library."]
+                unsafe fn add(&self, left: c_int, right: c_int) -> c_int;
+            }
+        };
+
+        let got = api_trait(&bindings, &config);
+
+        assert_eq!(
+            got.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn mocks_use_a_closure_for_each_function() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+                pub fn sum(count: c_int, ...) -> c_int;
+            }"#,
+        );
+        let should_be: ItemStruct = syn::parse_quote! {
+            #[doc = " A fake [`Bindings`] for use in tests, where each function calls a closure."]
+            #[doc = ""]
+            #[doc = " Calling a function which wasn't given a closure will panic."]
+            #[cfg(feature = "mock")]
+            #[derive(Default)]
+            pub struct MockBindings {
+                pub add: Option<Box<dyn Fn(c_int, c_int) -> c_int>>,
+                pub sum: Option<Box<dyn Fn() -> unsafe extern "C" fn(c_int, ...) -> c_int>>
+            }
+        };
+
+        let got =
+            mock_struct(&bindings, &Config::default(), "feature = \"mock\"");
+
+        assert_eq!(
+            got.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn linked_constructors_use_the_extern_symbols() {
+        let bindings = extract(
+            r#"extern "system" {
+                #[link_name = "\u{1}_add"]
+                pub fn add(left: c_int, right: c_int) -> c_int;
+                pub static mut counter: c_int;
+            }"#,
+        );
+        let should_be: Block = syn::parse_quote! {{
+            extern "system" {
+                #[link_name = "\u{1}_add"]
+                fn add(left: c_int, right: c_int) -> c_int;
+            }
+            extern "C" {
+                #[link_name = "counter"]
+                static mut counter: c_int;
+            }
+            Ok(Bindings {
+                add: add,
+                counter: BindingsStaticAddress(::std::ptr::addr_of_mut!(counter))
+            })
+        }};
+
+        let got = linked_constructor_body(&bindings, &Config::default());
+
+        assert_eq!(
+            got.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn load_is_only_reserved_when_generated() {
+        let src = "extern \"C\" { pub fn load(); }";
+        let config = Config {
+            library_search: Some(LibrarySearch::default()),
+            ..Default::default()
+        };
+
+        resolve_name_collisions(&mut extract(src), &Config::default()).unwrap();
+        let got = resolve_name_collisions(&mut extract(src), &config);
+
+        assert!(
+            matches!(got, Err(Error::NameCollision { name }) if name == "load")
+        );
+    }
+
+    #[test]
+    fn global_instances_can_contain_statics() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+                pub static VERSION: *const c_char;
+            }"#,
+        );
+        let config = Config {
+            global_instance: true,
+            ..Default::default()
+        };
+        let mut items = Vec::new();
+
+        append_new_bindings(&mut items, bindings, &config).unwrap();
+
+        // no Send or Sync impls are needed, the static's address is stored
+        // in a thread-safe wrapper
+        assert!(thread_safety_impls(&config).is_empty());
+    }
+
+    #[test]
+    fn serialised_calls_hold_the_lock() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+            }"#,
+        );
+        let config = Config {
+            thread_safety: ThreadSafety::Serialised,
+            ..Default::default()
+        };
+        let should_be: Block = syn::parse_quote! {{
+            let __guard = self._lock.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            (self.add)(left, right)
+        }};
+
+        let got = defer_to_function(&bindings.functions[0], &config);
+
+        assert_eq!(
+            got.block.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+        assert_eq!(thread_safety_impls(&config).len(), 2);
+        assert!(thread_safety_impls(&Config::default()).is_empty());
+    }
+
+    #[test]
+    fn free_functions_keep_the_original_signature() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, _: c_int) -> c_int;
+            }"#,
+        );
+        let should_be: ItemFn = syn::parse_quote! {
+            #[doc = " Calls the `add` function from the loaded library."]
+            pub unsafe fn add(left: c_int, arg2: c_int) -> c_int {
+                let __bindings = Bindings::global()
+                    .expect(concat!("The global ", stringify!(Bindings), " instance hasn't been set"));
+                __bindings.add(left, arg2)
+            }
+        };
+
+        let got = free_function(
+            &bindings.functions[0],
+            &Config::default(),
+            WhenUnloaded::Panic,
+        );
+
+        assert_eq!(
+            got.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn traced_calls_log_arguments_and_return_values() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: Foo) -> *mut c_void;
+            }"#,
+        );
+        let config = Config {
+            call_tracing: Some(CallTracing::Log),
+            ..Default::default()
+        };
+        let should_be: Block = syn::parse_quote! {{
+            ::log::trace!("Calling add(left = {:?}, right = ..)", left);
+            let __result = (self.add)(left, right);
+            ::log::trace!("add() returned {:?}", __result);
+            __result
+        }};
+
+        let got = defer_to_function(&bindings.functions[0], &config);
+
+        assert_eq!(
+            got.block.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn call_stats_are_only_kept_for_normal_functions() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+                pub fn sum(count: c_int, ...) -> c_int;
+            }"#,
+        );
+        let should_be: FieldValue = syn::parse_quote! {
+            _stats: ["add"]
+                .iter()
+                .map(|&name| (name, Default::default()))
+                .collect()
+        };
+
+        let got = new_stats(&bindings);
+
+        assert_eq!(
+            got.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn status_codes_are_converted_into_errors() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn open(path: *const c_char, _: c_int) -> c_int;
+            }"#,
+        );
+        let status_codes =
+            StatusCodes::new("crate::Error", "crate::Error::from_status");
+        let should_be: Block = syn::parse_quote! {{
+            let __status = self.open(path, arg2);
+            match __status {
+                0 => Ok(()),
+                __status => Err(crate::Error::from_status(__status)),
+            }
+        }};
+        let return_type: ReturnType =
+            syn::parse_quote!(-> Result<(), crate::Error>);
+
+        let got = checked_method(
+            &bindings.functions[0],
+            &Config::default(),
+            &status_codes,
+        );
+
+        assert_eq!(got.sig.ident, "open_checked");
+        assert_eq!(got.sig.output, return_type);
+        assert_eq!(
+            got.block.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn status_codes_must_be_integers() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn flag() -> bool;
+                pub fn name() -> *const c_char;
+                pub fn nothing();
+                pub fn code() -> ::std::os::raw::c_int;
+                pub fn size() -> usize;
+            }"#,
+        );
+        let config = Config {
+            status_codes: Some(StatusCodes::new("Error", "Error::from_code")),
+            ..Default::default()
+        };
+
+        for (i, valid) in [false, false, false, true, true].iter().enumerate() {
+            let mut func = bindings.functions[i].clone();
+            func.status_code = true;
+
+            let got = check_signature(&func, &config);
+
+            assert_eq!(got.is_ok(), *valid, "{}", func.item.sig.ident);
+            if !valid {
+                assert!(matches!(got, Err(Error::UnsupportedSignature { .. })));
+            }
+        }
+    }
+
+    #[test]
+    fn out_parameters_are_returned() {
+        let mut bindings = extract(
+            r#"extern "C" {
+                pub fn divmod(a: c_int, b: c_int, quotient: *mut c_int, remainder: *mut c_int) -> bool;
+            }"#,
+        );
+        bindings.functions[0].out_parameters = 2;
+        let should_be: Block = syn::parse_quote! {{
+            let mut quotient = ::std::mem::MaybeUninit::<c_int>::uninit();
+            let mut remainder = ::std::mem::MaybeUninit::<c_int>::uninit();
+            let __result = self.divmod(a, b, quotient.as_mut_ptr(), remainder.as_mut_ptr());
+            (__result, quotient.assume_init(), remainder.assume_init())
+        }};
+        let return_type: ReturnType =
+            syn::parse_quote!(-> (bool, c_int, c_int));
+
+        let got =
+            out_parameters_method(&bindings.functions[0], &Config::default());
+
+        assert_eq!(got.sig.ident, "divmod_out");
+        assert_eq!(got.sig.inputs.len(), 3);
+        assert_eq!(got.sig.output, return_type);
+        assert_eq!(
+            got.block.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn out_parameters_are_only_read_after_a_successful_call() {
+        // all-zeroes isn't a valid Kind, so it must never be zeroed
+        let mut bindings = extract(
+            r#"extern "C" {
+                pub fn get_kind(handle: *mut handle, kind: *mut Kind) -> c_int;
+            }"#,
+        );
+        bindings.functions[0].out_parameters = 1;
+        bindings.functions[0].status_code = true;
+        let config = Config {
+            status_codes: Some(StatusCodes::new("Error", "Error::from_code")),
+            ..Default::default()
+        };
+        let should_be: Block = syn::parse_quote! {{
+            let mut kind = ::std::mem::MaybeUninit::<Kind>::uninit();
+            let __status = self.get_kind(handle, kind.as_mut_ptr());
+            match __status {
+                0 => Ok(kind.assume_init()),
+                __status => Err(Error::from_code(__status)),
+            }
+        }};
+        let return_type: ReturnType = syn::parse_quote!(-> Result<Kind, Error>);
+
+        let got = out_parameters_method(&bindings.functions[0], &config);
+
+        assert_eq!(got.sig.output, return_type);
+        assert_eq!(
+            got.block.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn out_parameters_must_be_mutable_pointers() {
+        let mut bindings = extract(
+            r#"extern "C" {
+                pub fn get(a: *const c_int, b: *mut c_void);
+            }"#,
+        );
+
+        for count in 1..=3 {
+            bindings.functions[0].out_parameters = count;
+
+            let got =
+                check_signature(&bindings.functions[0], &Config::default());
+
+            assert!(matches!(got, Err(Error::UnsupportedSignature { .. })));
+        }
+    }
+
+    #[test]
+    fn statics_are_stored_in_a_thread_safe_wrapper() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub static VERSION: c_int;
+                pub static mut counter: c_int;
+            }"#,
+        );
+        let immutable: Type =
+            syn::parse_quote!(BindingsStaticAddress<*const c_int>);
+        let mutable: Type =
+            syn::parse_quote!(BindingsStaticAddress<*mut c_int>);
+        let accessor: Block = syn::parse_quote! {{
+            &*self.VERSION.as_ptr()
+        }};
+
+        let vtable = bindings_vtable(&bindings, &Config::default());
+        let types: Vec<_> = vtable.fields.iter().map(|f| &f.ty).collect();
+        let got = static_accessor(&bindings.statics[0], &Config::default());
+
+        assert_eq!(&types[1..], &[&immutable, &mutable]);
+        assert_eq!(
+            got.block.to_token_stream().to_string(),
+            accessor.to_token_stream().to_string()
+        );
+        // the wrapper, its as_ptr() method, and Send + Sync for both
+        // pointer types
+        assert_eq!(static_address(&Config::default()).len(), 6);
+    }
+
+    #[test]
+    fn symbols_are_loaded_without_shadowing_statics() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+                pub static VERSION: c_int;
+            }"#,
+        );
+        let should_be: Block = syn::parse_quote! {{
+            Ok(Bindings {
+                add: *library.get(b"add")?,
+                VERSION: BindingsStaticAddress(*library.get(b"VERSION")?),
+                _library: library
+            })
+        }};
+
+        let got = from_library(&bindings, &Config::default());
+
+        assert_eq!(
+            got.block.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn serialised_free_functions_hold_the_lock() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+            }"#,
+        );
+        let config = Config {
+            thread_safety: ThreadSafety::Serialised,
+            ..Default::default()
+        };
+        let call: Expr = syn::parse_quote!(__bindings.add(left, right));
+
+        let free =
+            free_function(&bindings.functions[0], &config, WhenUnloaded::Panic);
+
+        // the wrapper method takes the lock for us
+        assert_eq!(free.block.stmts.len(), 2);
+        assert_eq!(
+            free.block.stmts[1].to_token_stream().to_string(),
+            call.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn serialised_bindings_need_wrappers() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+            }"#,
+        );
+        let config = Config {
+            thread_safety: ThreadSafety::Serialised,
+            emit_wrappers: false,
+            ..Default::default()
+        };
+        let mut items = Vec::new();
+
+        let got = append_new_bindings(&mut items, bindings, &config);
+
+        assert!(matches!(got, Err(Error::IncompatibleOptions { .. })));
+    }
+
+    #[test]
+    fn private_free_functions_are_visible_to_the_parent_module() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+            }"#,
+        );
+        let mut config = Config {
+            visibility: crate::Visibility::Private,
+            ..Default::default()
+        };
+        let pub_super: Visibility = syn::parse_quote!(pub(super));
+        let pub_crate: Visibility = syn::parse_quote!(pub(crate));
+
+        let got =
+            free_function(&bindings.functions[0], &config, WhenUnloaded::Panic);
+
+        assert_eq!(got.vis, pub_super);
+
+        config.visibility = crate::Visibility::Crate;
+        let got =
+            free_function(&bindings.functions[0], &config, WhenUnloaded::Panic);
+
+        assert_eq!(got.vis, pub_crate);
+    }
+
+    #[test]
+    fn free_functions_which_never_return_always_panic() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn exit(code: c_int) -> !;
+            }"#,
+        );
+        let return_type: ReturnType = syn::parse_quote!(-> !);
+
+        let got = free_function(
+            &bindings.functions[0],
+            &Config::default(),
+            WhenUnloaded::ReturnError,
+        );
+
+        assert_eq!(got.sig.output, return_type);
+    }
+
+    #[test]
+    fn free_functions_use_the_original_name() {
+        let mut bindings = extract(
+            r#"extern "C" {
+                #[link_name = "\u{1}_load_from_path"]
+                pub fn load_from_path(path: *const c_char) -> c_int;
+            }"#,
+        );
+        let config = Config {
+            name_collision_suffix: Some(String::from("_c")),
+            ..Default::default()
+        };
+        resolve_name_collisions(&mut bindings, &config).unwrap();
+        let should_be: Block = syn::parse_quote! {{
+            let __bindings = Bindings::global()
+                .ok_or(BindingsUnavailable { function: "_load_from_path" })?;
+            Ok(__bindings.load_from_path_c(path))
+        }};
+
+        let got = free_function(
+            &bindings.functions[0],
+            &config,
+            WhenUnloaded::ReturnError,
+        );
+
+        assert_eq!(got.sig.ident, "load_from_path");
+        assert_eq!(
+            got.block.to_token_stream().to_string(),
+            should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn optional_functions_may_be_missing() {
+        let mut bindings = extract(
+            r#"extern "C" {
+                pub fn add(left: c_int, right: c_int) -> c_int;
+            }"#,
+        );
+        bindings.functions[0].optional = true;
+        let func = &bindings.functions[0];
+        let library: ExprPath = syn::parse_quote!(library);
+        let field_should_be: Type = syn::parse_quote! {
+            Option<unsafe extern "C" fn(c_int, c_int) -> c_int>
+        };
+        let load_should_be: FieldValue = syn::parse_quote! {
+            add: library.get(b"add").ok().map(|symbol| *symbol)
+        };
+        let wrapper_should_be: ImplItemMethod = syn::parse_quote! {
+            pub unsafe fn add(&self, left: c_int, right: c_int) -> Option<c_int> {
+                Some((self.add?)(left, right))
+            }
+        };
+
+        let field = field_type(func);
+        let load =
+            load_symbol(&library, &func.item.sig.ident, &func.symbol, true);
+        let mut wrapper = defer_to_function(func, &Config::default());
+        wrapper.attrs.clear();
+
+        assert_eq!(
+            field.to_token_stream().to_string(),
+            field_should_be.to_token_stream().to_string()
+        );
+        assert_eq!(
+            load.to_token_stream().to_string(),
+            load_should_be.to_token_stream().to_string()
+        );
+        assert_eq!(
+            wrapper.to_token_stream().to_string(),
+            wrapper_should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn constructors_defer_to_from_library() {
+        let config = Config {
+            constructor_names: ConstructorNames {
+                from_library: String::from("with_library"),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let from_unix_should_be: ImplItemMethod = syn::parse_quote! {
+            #[cfg(unix)]
+            pub unsafe fn from_unix_library(
+                library: ::libloading::os::unix::Library
+            ) -> Result<Self, ::libloading::Error> {
+                Self::with_library(library.into())
+            }
+        };
+        let load_from_path_should_be: Block = syn::parse_quote! {{
+            let library = ::libloading::Library::new(path)?;
+            Self::with_library(library)
+        }};
+
+        let from_unix = from_unix_library(&config);
+        let load_from_path = load_from_path(&config);
+
+        assert_eq!(
+            from_unix.to_token_stream().to_string(),
+            from_unix_should_be.to_token_stream().to_string()
+        );
+        assert_eq!(
+            load_from_path.block.to_token_stream().to_string(),
+            load_from_path_should_be.to_token_stream().to_string()
+        );
+    }
+
+    #[test]
+    fn mocks_of_functions_which_never_return_panic() {
+        let bindings = extract(
+            r#"extern "C" {
+                pub fn exit(code: c_int) -> !;
+            }"#,
+        );
+        let config = Config::default();
+        let closure_should_be: Type = syn::parse_quote!(dyn Fn(c_int));
+
+        let method = defer_to_function(&bindings.functions[0], &config);
+        let closure = mock_closure_type(&method.sig);
+        let mock = mock_impl(&bindings, &config, "test");
+
+        assert_eq!(
+            closure.to_token_stream().to_string(),
+            closure_should_be.to_token_stream().to_string()
+        );
+        let body = mock.items[0].to_token_stream().to_string();
+        assert!(body.contains("panic !"), "{}", body);
     }
 }
@@ -3,38 +3,232 @@
 extern crate pretty_assertions;
 
 mod bindings;
+mod config;
+mod error;
 mod gen;
 
-use bindgen::Builder;
-use syn::{parse::Error as ParseError, File, ForeignItemFn};
+pub use crate::{
+    config::{
+        Builder, CallTracing, Config, ConstructorNames, ExternDeclarations,
+        FreeFunctions, LibrarySearch, StatusCodes, SymbolResolution,
+        ThreadSafety, Visibility, WhenUnloaded,
+    },
+    error::Error,
+};
 
+use syn::{
+    File, ForeignItem, ForeignItemFn, ForeignItemStatic, Ident, Meta, Path,
+    Type,
+};
+
+/// Hooks for customising which items get bindings and how they are generated.
+///
+/// Only [`BindingStrategy::should_include()`] is required, everything else
+/// has a sensible default.
 pub trait BindingStrategy {
     fn should_include(&self, item: &ForeignItemFn) -> bool;
+
+    /// Should we generate an accessor for this static?
+    fn should_include_static(&self, _item: &ForeignItemStatic) -> bool { true }
+
+    /// The name to use for this function's method (and field) on the
+    /// generated struct.
+    fn method_name(&self, item: &ForeignItemFn) -> String {
+        item.sig.ident.to_string()
+    }
+
+    /// The symbol to look up when loading this function, where `link_name`
+    /// is the name bindgen says the function is linked as.
+    fn symbol_name(&self, _item: &ForeignItemFn, link_name: &str) -> String {
+        link_name.to_string()
+    }
+
+    /// Force a function to use a different ABI to the `extern` block it was
+    /// declared in (`block_abi`), returning `None` to keep it as-is.
+    ///
+    /// Blocks without an explicit ABI (`extern {}`) are treated as `"C"`.
+    /// Returning an ABI `rustc` doesn't know about results in an
+    /// [`Error::InvalidAbi`].
+    fn abi(&self, _item: &ForeignItemFn, _block_abi: &str) -> Option<String> {
+        None
+    }
+
+    /// Should this function be resolved leniently?
+    ///
+    /// Optional functions are stored as an `Option<unsafe extern fn(...)>`
+    /// so a missing symbol won't make `load_from_path()` fail, and their
+    /// wrapper method will return `None` instead of calling the function.
+    /// This is handy when targeting several versions of the same library.
+    fn is_optional(&self, _item: &ForeignItemFn) -> bool { false }
+
+    /// Does this function return a status code, where zero means success?
+    ///
+    /// These functions get an extra `*_checked()` method which returns a
+    /// `Result`, using [`Config::status_codes`] to create the error. The
+    /// function must return an integer (e.g. `c_int`), otherwise generating
+    /// the bindings fails with [`Error::UnsupportedSignature`].
+    fn returns_status_code(&self, _item: &ForeignItemFn) -> bool { false }
+
+    /// How many of this function's trailing arguments are out-parameters
+    /// (`*mut T` pointers the function writes its results to)?
+    ///
+    /// Functions with out-parameters get an extra `*_out()` method which
+    /// passes in uninitialised values and returns them after the call. When
+    /// the function also returns a status code, the values are returned as a
+    /// `Result`.
+    ///
+    /// The function must write to every out-parameter (or, for functions
+    /// returning a status code, every time it succeeds), otherwise the
+    /// `*_out()` method will read uninitialised memory.
+    fn out_parameters(&self, _item: &ForeignItemFn) -> usize { 0 }
+
+    /// Should the original `extern` declaration for this function or static
+    /// be kept in the output?
+    ///
+    /// This lets the same bindings be used when the library is linked
+    /// normally, in addition to being loaded at runtime.
+    fn keep_extern_declaration(&self, _item: &ForeignItem) -> bool { false }
 }
 
 pub fn generate_bindings<S>(
-    builder: Builder,
+    builder: bindgen::Builder,
     strategy: &S,
 ) -> Result<File, Error>
 where
     S: BindingStrategy,
 {
-    let raw_bindings = builder
-        .generate()
-        .map_err(|_| Error::BindgenFailed)?
-        .to_string();
-    let mut file: File = syn::parse_str(&raw_bindings)?;
+    generate_bindings_with_config(builder, strategy, &Config::default())
+}
+
+pub fn generate_bindings_with_config<S>(
+    builder: bindgen::Builder,
+    strategy: &S,
+    config: &Config,
+) -> Result<File, Error>
+where
+    S: BindingStrategy,
+{
+    validate_names(config)?;
+
+    if let Some(cfg) = &config.mock_cfg {
+        if syn::parse_str::<Meta>(cfg).is_err() {
+            return Err(Error::InvalidCfg { cfg: cfg.clone() });
+        }
+    }
 
-    let bindings = crate::bindings::extract_raw_bindings(&mut file, strategy);
-    gen::append_new_bindings(&mut file.items, bindings);
+    if let Some(status_codes) = &config.status_codes {
+        if syn::parse_str::<Type>(&status_codes.error_type).is_err() {
+            return Err(Error::InvalidName {
+                name: status_codes.error_type.clone(),
+                what: "status code error type",
+            });
+        }
+        if syn::parse_str::<Path>(&status_codes.conversion).is_err() {
+            return Err(Error::InvalidName {
+                name: status_codes.conversion.clone(),
+                what: "status code conversion function",
+            });
+        }
+    }
+
+    let bindgen_failed = Error::bindgen_failed(&builder);
+    let raw_bindings =
+        builder.generate().map_err(|_| bindgen_failed)?.to_string();
+    let mut file: File = syn::parse_str(&raw_bindings)
+        .map_err(|e| Error::parse(e, &raw_bindings))?;
+
+    let bindings =
+        crate::bindings::extract_raw_bindings(&mut file, strategy, config)?;
+    gen::append_new_bindings(&mut file.items, bindings, config)?;
 
     Ok(file)
 }
 
-#[derive(Debug, thiserror::Error)]
-pub enum Error {
-    #[error("Bindgen was unable to generate bindings")]
-    BindgenFailed,
-    #[error("Unable to parse the bindings emitted by bindgen")]
-    Parse(#[from] ParseError),
+/// Make sure the names we were given are valid identifiers before we try to
+/// use them.
+fn validate_names(config: &Config) -> Result<(), Error> {
+    let ConstructorNames {
+        load_from_path,
+        from_library,
+        from_unix_library,
+        load,
+    } = &config.constructor_names;
+
+    let mut names = vec![
+        (&config.struct_name, "generated struct"),
+        (load_from_path, "load_from_path() constructor"),
+        (from_library, "from_library() constructor"),
+        (from_unix_library, "from_unix_library() constructor"),
+        (load, "load() constructor"),
+    ];
+
+    if let Some(free_functions) = &config.free_functions {
+        names.push((&free_functions.module, "free functions module"));
+    }
+
+    // the suffix is appended to existing names, so it only needs to be valid
+    // after the first character
+    if let Some(suffix) = &config.name_collision_suffix {
+        if syn::parse_str::<Ident>(&format!("x{}", suffix)).is_err() {
+            return Err(Error::InvalidName {
+                name: suffix.clone(),
+                what: "name collision suffix",
+            });
+        }
+    }
+
+    for (name, what) in names {
+        // raw identifiers (e.g. "r#type") are rejected by Ident::new()
+        if name.starts_with("r#") || syn::parse_str::<Ident>(name).is_err() {
+            return Err(Error::InvalidName {
+                name: name.clone(),
+                what,
+            });
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_must_be_identifiers() {
+        for invalid in &["r#Foo", "not valid", ""] {
+            let config = Config {
+                struct_name: invalid.to_string(),
+                ..Default::default()
+            };
+
+            let got = validate_names(&config);
+
+            assert!(
+                matches!(got, Err(Error::InvalidName { ref name, .. }) if name == invalid),
+                "{:?}",
+                got
+            );
+        }
+
+        validate_names(&Config::default()).unwrap();
+    }
+
+    #[test]
+    fn name_collision_suffixes_must_continue_an_identifier() {
+        let config = Config {
+            name_collision_suffix: Some(String::from("-x")),
+            ..Default::default()
+        };
+
+        let got = validate_names(&config);
+
+        assert!(matches!(
+            got,
+            Err(Error::InvalidName {
+                what: "name collision suffix",
+                ..
+            })
+        ));
+    }
 }
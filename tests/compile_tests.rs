@@ -1,10 +1,14 @@
 use anyhow::{Context, Error};
 use cbindgen::{Config, Language};
-use libloading_bindgen::BindingStrategy;
+use libloading_bindgen::{
+    BindingStrategy, Config as BindingsConfig, ExternDeclarations,
+    LibrarySearch, SymbolResolution,
+};
 use quote::ToTokens;
 use std::{
     env,
     fs::File,
+    io::Write,
     path::{Path, PathBuf},
     process::{Command, Stdio},
 };
@@ -13,6 +17,9 @@ use tempfile::Builder;
 
 macro_rules! integration_test {
     ($directory:ident) => {
+        integration_test!($directory, BindingsConfig::default());
+    };
+    ($directory:ident, $config:expr) => {
         #[test]
         fn $directory() -> Result<(), Error> {
             let name = stringify!($directory);
@@ -25,7 +32,9 @@ macro_rules! integration_test {
                 .prefix("libloading-bindgen-")
                 .tempdir()
                 .context("Unable to create a temp directory")?;
-            let ret = compile_and_test(name, &test_dir, output_dir.path());
+            let config = $config;
+            let ret =
+                compile_and_test(name, &test_dir, output_dir.path(), &config);
 
             if env::var("RETAIN_GENERATED_CODE").is_ok() || ret.is_err() {
                 let persisted_path = output_dir.into_path();
@@ -44,11 +53,38 @@ macro_rules! integration_test {
 }
 
 integration_test!(smoke_test);
+integration_test!(statics);
+integration_test!(variadic);
+integration_test!(optional, {
+    let mut config = BindingsConfig::default();
+    config.symbol_resolution = SymbolResolution::Lenient;
+    config
+});
+integration_test!(keep_extern_declarations, {
+    let mut config = BindingsConfig::default();
+    config.extern_declarations = ExternDeclarations::KeepAll;
+    config
+});
+integration_test!(search, {
+    let mut search = LibrarySearch::default();
+    search.file_names = vec![
+        String::from("libsearch_missing.so"),
+        String::from("libsearch_native.so"),
+    ];
+    search.directories = vec![String::from("/nonexistent-libloading-bindgen")];
+    search.env_vars =
+        vec![String::from("SEARCH_FILE"), String::from("SEARCH_DIR")];
+
+    let mut config = BindingsConfig::default();
+    config.library_search = Some(search);
+    config
+});
 
 fn compile_and_test(
     name: &str,
     test_dir: &Path,
     output_dir: &Path,
+    config: &BindingsConfig,
 ) -> Result<(), Error> {
     let library_code = test_dir.join("native.rs");
     let native_dir = output_dir.join("native");
@@ -62,6 +98,7 @@ fn compile_and_test(
         output_dir.join(name),
         &native_dir,
         &test_code,
+        config,
     )?;
 
     let output = Command::new("cargo")
@@ -103,6 +140,7 @@ fn generate_test_binary<P>(
     output_dir: P,
     native_dir: &Path,
     test_code: &Path,
+    config: &BindingsConfig,
 ) -> Result<PathBuf, Error>
 where
     P: AsRef<Path>,
@@ -136,16 +174,31 @@ where
     let bindings_h = output_dir.join("bindings.h");
     let mut f = File::create(&bindings_h)?;
     c_bindings.write(&mut f);
+
+    // some things (e.g. variadic functions) can't be expressed with cbindgen,
+    // so tests can provide extra declarations to append to the header
+    let extra_declarations = test_code.with_file_name("extra.h");
+    if extra_declarations.exists() {
+        let extra = std::fs::read(&extra_declarations).with_context(|| {
+            format!("Couldn't read \"{}\"", extra_declarations.display())
+        })?;
+        f.write_all(&extra)?;
+    }
+
     f.sync_all()?;
 
     let strategy = StartsWithName { name };
     let bindgen_builder = bindgen::builder()
         .header(bindings_h.display().to_string())
-        .whitelist_function(format!("{}.*", name));
-    let rust_bindings =
-        libloading_bindgen::generate_bindings(bindgen_builder, &strategy)?
-            .to_token_stream()
-            .to_string();
+        .whitelist_function(format!("{}.*", name))
+        .whitelist_var(format!("{}.*", name));
+    let rust_bindings = libloading_bindgen::Builder::with_config(
+        bindgen_builder,
+        config.clone(),
+    )
+    .generate(&strategy)?
+    .to_token_stream()
+    .to_string();
 
     let bindings_rs = src_dir.join("bindings.rs");
     std::fs::write(&bindings_rs, rust_bindings.as_bytes()).with_context(
@@ -193,7 +246,7 @@ where
 {
     let output_dir = output_dir.as_ref();
 
-    ensure_directory_exists(&output_dir)?;
+    ensure_directory_exists(output_dir)?;
 
     let cargo_toml = output_dir.join("Cargo.toml");
     let cargo_toml_src = NATIVE_CARGO_TOML.replace("$PACKAGE_NAME", name);
@@ -251,6 +304,6 @@ fn native_library_name(test_name: &str) -> String {
 }
 
 fn ensure_directory_exists(dir: &Path) -> Result<(), Error> {
-    std::fs::create_dir_all(&dir)
+    std::fs::create_dir_all(dir)
         .with_context(|| format!("Couldn't create \"{}\"", dir.display()))
 }
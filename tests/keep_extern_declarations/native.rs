@@ -0,0 +1,13 @@
+use std::os::raw::c_int;
+
+#[no_mangle]
+pub static keep_extern_declarations_answer: c_int = 42;
+
+#[no_mangle]
+pub static mut keep_extern_declarations_counter: c_int = 0;
+
+#[no_mangle]
+pub unsafe extern "C" fn keep_extern_declarations_increment() -> c_int {
+    keep_extern_declarations_counter += 1;
+    keep_extern_declarations_counter
+}
@@ -0,0 +1,18 @@
+mod bindings;
+
+use bindings::Bindings;
+use std::{env, error::Error};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let library_path = env::args().skip(1).next().expect("USAGE: test <dylib>");
+
+    unsafe {
+        let vtable = Bindings::load_from_path(&library_path)?;
+
+        assert_eq!(*vtable.keep_extern_declarations_answer(), 42);
+        assert_eq!(vtable.keep_extern_declarations_increment(), 1);
+        assert_eq!(*vtable.keep_extern_declarations_counter(), 1);
+    }
+
+    Ok(())
+}
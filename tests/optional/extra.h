@@ -0,0 +1 @@
+int optional_missing(int value);
@@ -0,0 +1,4 @@
+use std::os::raw::c_int;
+
+#[no_mangle]
+pub extern "C" fn optional_double(value: c_int) -> c_int { value * 2 }
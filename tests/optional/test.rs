@@ -0,0 +1,18 @@
+mod bindings;
+
+use bindings::Bindings;
+use std::{env, error::Error};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let library_path = env::args().skip(1).next().expect("USAGE: test <dylib>");
+
+    unsafe {
+        // loading still works when an optional symbol is missing
+        let vtable = Bindings::load_from_path(&library_path)?;
+
+        assert_eq!(vtable.optional_double(2), Some(4));
+        assert_eq!(vtable.optional_missing(2), None);
+    }
+
+    Ok(())
+}
@@ -0,0 +1,6 @@
+use std::os::raw::c_int;
+
+#[no_mangle]
+pub extern "C" fn search_add(left: c_int, right: c_int) -> c_int {
+    left + right
+}
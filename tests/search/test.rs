@@ -0,0 +1,47 @@
+mod bindings;
+
+use bindings::Bindings;
+use std::{env, error::Error, path::PathBuf};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let library_path = env::args().skip(1).next().expect("USAGE: test <dylib>");
+    let library_path = PathBuf::from(library_path);
+    let library_dir = library_path.parent().unwrap();
+
+    // a directory which exists, but doesn't contain the library
+    let empty_dir = env::temp_dir().join("libloading-bindgen-search-test");
+    std::fs::create_dir_all(&empty_dir)?;
+
+    unsafe {
+        // every candidate is reported, in the order they were tried
+        env::set_var("SEARCH_FILE", "/nonexistent/libsearch.so");
+        env::set_var("SEARCH_DIR", &empty_dir);
+
+        let error = Bindings::load().err().expect("Nothing should be found");
+        let attempts: Vec<_> =
+            error.attempts.iter().map(|(path, _)| path.clone()).collect();
+        let should_be: Vec<PathBuf> = vec![
+            "/nonexistent/libsearch.so".into(),
+            empty_dir.join("libsearch_missing.so"),
+            empty_dir.join("libsearch_native.so"),
+            "/nonexistent-libloading-bindgen/libsearch_missing.so".into(),
+            "/nonexistent-libloading-bindgen/libsearch_native.so".into(),
+            "libsearch_missing.so".into(),
+            "libsearch_native.so".into(),
+        ];
+        assert_eq!(attempts, should_be);
+
+        // an environment variable can point at the library itself
+        env::set_var("SEARCH_FILE", &library_path);
+        let bindings = Bindings::load()?;
+        assert_eq!(bindings.search_add(1, 2), 3);
+
+        // or at the directory containing it
+        env::remove_var("SEARCH_FILE");
+        env::set_var("SEARCH_DIR", library_dir);
+        let bindings = Bindings::load()?;
+        assert_eq!(bindings.search_add(1, 2), 3);
+    }
+
+    Ok(())
+}
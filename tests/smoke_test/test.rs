@@ -13,5 +13,21 @@ fn main() -> Result<(), Box<dyn Error>> {
         assert_eq!(got, 3);
     }
 
+    // libraries can also be opened by the caller
+    unsafe {
+        let library = libloading::Library::new(&library_path)?;
+        let vtable = Bindings::from_library(library)?;
+        assert_eq!(vtable.smoke_test_add(1, 2), 3);
+    }
+
+    #[cfg(unix)]
+    unsafe {
+        use libloading::os::unix::{Library, RTLD_GLOBAL, RTLD_NOW};
+
+        let library = Library::open(Some(&library_path), RTLD_NOW | RTLD_GLOBAL)?;
+        let vtable = Bindings::from_unix_library(library)?;
+        assert_eq!(vtable.smoke_test_add(1, 2), 3);
+    }
+
     Ok(())
 }
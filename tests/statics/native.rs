@@ -0,0 +1,13 @@
+use std::os::raw::c_int;
+
+#[no_mangle]
+pub static statics_answer: c_int = 42;
+
+#[no_mangle]
+pub static mut statics_counter: c_int = 0;
+
+#[no_mangle]
+pub unsafe extern "C" fn statics_increment() -> c_int {
+    statics_counter += 1;
+    statics_counter
+}
@@ -0,0 +1,2 @@
+
+int variadic_sum(int count, ...);
@@ -0,0 +1,13 @@
+use std::os::raw::c_int;
+
+#[no_mangle]
+pub extern "C" fn variadic_add(left: c_int, right: c_int) -> c_int {
+    left + right
+}
+
+// Variadic functions can't be defined on stable Rust, so we just need
+// *something* with the right name for the loader to find. It isn't `pub` so
+// cbindgen will skip it and the variadic declaration from `extra.h` is used
+// instead.
+#[no_mangle]
+extern "C" fn variadic_sum(count: c_int) -> c_int { count }
@@ -0,0 +1,23 @@
+mod bindings;
+
+use bindings::Bindings;
+use std::{env, error::Error, os::raw::c_int};
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let library_path = env::args().skip(1).next().expect("USAGE: test <dylib>");
+
+    unsafe {
+        let vtable = Bindings::load_from_path(&library_path)?;
+
+        // non-variadic functions are wrapped as normal
+        let got = vtable.variadic_add(1, 2);
+        assert_eq!(got, 3);
+
+        // but variadic ones just give you the function pointer
+        let variadic_sum: unsafe extern "C" fn(c_int, ...) -> c_int =
+            vtable.variadic_sum();
+        assert_ne!(variadic_sum as usize, 0);
+    }
+
+    Ok(())
+}